@@ -0,0 +1,227 @@
+use crate::breathe;
+use anyhow::{anyhow, Context};
+use std::f64::consts::TAU;
+use std::io::Write;
+
+/// How much the tone rises, relative to the base pitch, when the lungs are full
+const PITCH_RANGE: f64 = 0.5;
+/// The chime is some octaves above the base pitch
+const CHIME_RATIO: f64 = 4.0;
+/// Seconds for the chime to fade
+const CHIME_LENGTH: f64 = 0.6;
+/// Seconds of fade in and fade out of the whole track, to avoid clicks
+const FADE_LENGTH: f64 = 0.05;
+
+/// Parameters of the synthesized track
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct AudioOpt {
+    /// frequency of the tone, in Hz, when the lungs are empty
+    pub(crate) pitch: f64,
+    /// from 0 to 1
+    pub(crate) volume: f64,
+    pub(crate) sample_rate: u32,
+}
+
+/// The number of samples of the session
+fn sample_count(timeline: &breathe::Timeline, opt: &AudioOpt) -> u64 {
+    timeline.session_length() * u64::from(opt.sample_rate)
+}
+
+/// Synthesize the session as mono samples: a tone rising while breathing in,
+/// steady while holding and falling while breathing out, with a chime at every phase change.
+/// The samples are computed one at a time, the track is never held in memory
+pub(crate) fn synthesize<'a>(
+    timeline: &'a breathe::Timeline,
+    opt: &AudioOpt,
+) -> impl Iterator<Item = i16> + 'a {
+    let opt = *opt;
+    let rate = f64::from(opt.sample_rate);
+    let volume = opt.volume.clamp(0.0, 1.0);
+    let total = sample_count(timeline, &opt);
+    let fade = ((FADE_LENGTH * rate) as u64).min(total / 2);
+    let chime_frequency = opt.pitch * CHIME_RATIO;
+    // the phase of the oscillator is carried over the segments, so that the tone is continuous
+    let mut phi = 0.0;
+    timeline
+        .segments()
+        .iter()
+        .flat_map(move |segment| {
+            (0..segment.duration * u64::from(opt.sample_rate)).map(move |i| (segment, i))
+        })
+        .zip(0u64..)
+        .map(move |((segment, i), n)| {
+            let phase_length = timeline.phase_length(segment.phase) as f64;
+            let t = i as f64 / rate;
+            let progress = segment.phase.easing().apply(t / phase_length);
+            let frequency = opt.pitch * (1.0 + PITCH_RANGE * segment.phase.fill(progress));
+            phi = (phi + TAU * frequency / rate) % TAU;
+            let tone = 0.6 * phi.sin();
+            let chime = if t < CHIME_LENGTH {
+                0.4 * (-t * 8.0).exp() * (TAU * chime_frequency * t).sin()
+            } else {
+                0.0
+            };
+            let edge = n.min(total - 1 - n);
+            let envelope = if edge < fade {
+                edge as f64 / fade as f64
+            } else {
+                1.0
+            };
+            ((tone + chime) * envelope * volume * f64::from(i16::MAX)).round() as i16
+        })
+}
+
+/// The size of the WAV data, failing beyond the 4 GiB limit of the format
+fn data_len(count: u64) -> std::io::Result<u32> {
+    count
+        .checked_mul(2)
+        .and_then(|len| len.checked_add(36))
+        .and_then(|len| u32::try_from(len).ok())
+        .map(|len| len - 36)
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the track is too long for the WAV format",
+            )
+        })
+}
+
+/// Write `count` 16 bit mono PCM samples in the WAV format
+pub(crate) fn write_wav<W: Write>(
+    mut writer: W,
+    samples: impl IntoIterator<Item = i16>,
+    count: u64,
+    sample_rate: u32,
+) -> std::io::Result<()> {
+    let data_len = data_len(count)?;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_len).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    // PCM, mono
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * 2).to_le_bytes())?;
+    // block align and bits per sample
+    writer.write_all(&2u16.to_le_bytes())?;
+    writer.write_all(&16u16.to_le_bytes())?;
+    writer.write_all(b"data")?;
+    writer.write_all(&data_len.to_le_bytes())?;
+    for sample in samples.into_iter().take(count as usize) {
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Synthesize the session of a pattern and save it in `output`
+pub(crate) fn render(
+    opt: &breathe::BreathSessionOpt,
+    audio_opt: &AudioOpt,
+    output: &std::path::Path,
+) -> anyhow::Result<()> {
+    if audio_opt.pitch <= 0.0
+        || audio_opt.pitch * CHIME_RATIO >= f64::from(audio_opt.sample_rate) / 2.0
+    {
+        return Err(anyhow!(
+            "Invalid pitch {}: too high for a sample rate of {}",
+            audio_opt.pitch,
+            audio_opt.sample_rate
+        ));
+    }
+    let session = breathe::BreathingSession::with_opt(opt)?;
+    let count = sample_count(session.timeline(), audio_opt);
+    // fail before synthesizing anything
+    data_len(count).with_context(|| {
+        format!(
+            "The session of {} seconds can't be rendered at {} Hz",
+            session.session_length, audio_opt.sample_rate
+        )
+    })?;
+    let file = std::fs::File::create(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    write_wav(
+        std::io::BufWriter::new(file),
+        synthesize(session.timeline(), audio_opt),
+        count,
+        audio_opt.sample_rate,
+    )
+    .with_context(|| format!("Failed to write {}", output.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Pattern, PatternLength};
+
+    fn timeline(pattern: &Pattern) -> breathe::Timeline {
+        breathe::BreathingSession::with_opt(&breathe::BreathSessionOpt { pattern })
+            .unwrap()
+            .timeline()
+            .clone()
+    }
+
+    /// Sign changes from negative to positive, roughly the frequency when counted over a second
+    fn zero_crossings(samples: &[i16]) -> usize {
+        samples.windows(2).filter(|w| w[0] < 0 && w[1] >= 0).count()
+    }
+
+    fn peak(samples: &[i16]) -> i32 {
+        samples.iter().map(|s| i32::from(*s).abs()).max().unwrap()
+    }
+
+    #[test]
+    fn synthesize_tones() {
+        let pattern = Pattern {
+            breath_in: 2,
+            hold_in: Some(2),
+            breath_out: 2,
+            pattern_length: Some(PatternLength::Iterations(1)),
+            ..Default::default()
+        };
+        let opt = AudioOpt {
+            pitch: 200.0,
+            volume: 1.0,
+            sample_rate: 8000,
+        };
+        let timeline = timeline(&pattern);
+        let samples: Vec<i16> = synthesize(&timeline, &opt).collect();
+        assert_eq!(samples.len(), 6 * 8000);
+        let second = |s: usize| &samples[s * 8000..(s + 1) * 8000];
+        // rising while breathing in, up to 1.5 times the pitch while holding, then falling
+        assert!(zero_crossings(second(0)) < zero_crossings(second(1)));
+        assert!((295..=305).contains(&zero_crossings(second(3))));
+        assert!(zero_crossings(second(4)) > zero_crossings(second(5)));
+        // the chime is louder than the tone alone
+        let hold_start = &samples[2 * 8000..2 * 8000 + 800];
+        let hold_end = &samples[4 * 8000 - 800..4 * 8000];
+        assert!(peak(hold_start) > peak(hold_end));
+        // fade in and out
+        assert_eq!(samples[0], 0);
+        assert_eq!(*samples.last().unwrap(), 0);
+
+        let quiet: Vec<i16> = synthesize(&timeline, &AudioOpt { volume: 0.1, ..opt }).collect();
+        assert!(peak(&quiet) * 5 < peak(&samples));
+    }
+
+    #[test]
+    fn wav_header() {
+        let mut out = Vec::new();
+        write_wav(&mut out, [0, 1, -1], 3, 8000).unwrap();
+        assert_eq!(out.len(), 44 + 6);
+        assert_eq!(&out[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(out[4..8].try_into().unwrap()), 42);
+        assert_eq!(&out[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(out[24..28].try_into().unwrap()), 8000);
+        assert_eq!(&out[36..40], b"data");
+        assert_eq!(u32::from_le_bytes(out[40..44].try_into().unwrap()), 6);
+        assert_eq!(&out[44..], &[0, 0, 1, 0, 0xff, 0xff]);
+        assert_eq!(
+            data_len((u64::from(u32::MAX) - 36) / 2).unwrap(),
+            u32::MAX - 37
+        );
+        assert!(data_len(u64::from(u32::MAX) / 2).is_err());
+        assert!(write_wav(Vec::new(), std::iter::empty(), u64::MAX, 8000).is_err());
+    }
+}
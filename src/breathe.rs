@@ -1,10 +1,48 @@
-use crate::config::{Pattern, PatternLength};
+use crate::config::{EndPolicy, Pattern, PatternLength, PhaseLabels};
+use crate::i18n::{self, Message};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use strum::{Display, IntoStaticStr};
 
+/// The longest session, in seconds, before the end policy: the timeline is computed up front
+const MAX_SESSION_LENGTH: u64 = 24 * 3600;
+
+/// Reasons why a pattern can't be turned into a breathing session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SessionError {
+    /// The pattern has no session length (neither time nor iterations)
+    MissingPatternLength,
+    /// All the phases of the pattern are 0 seconds long
+    EmptyCycle,
+    /// The session is longer than `MAX_SESSION_LENGTH`
+    TooLong,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::MissingPatternLength => {
+                write!(f, "The pattern doesn't specify a session length")
+            }
+            SessionError::EmptyCycle => {
+                write!(f, "All the phases of the pattern are 0 seconds long")
+            }
+            SessionError::TooLong => write!(
+                f,
+                "The session is too long, the limit is {} hours",
+                MAX_SESSION_LENGTH / 3600
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
 /// Breathing can be in 4 possible phases.
 /// This struct represent those 4 possible values
-#[derive(Debug, Default, Copy, Clone, PartialEq, Hash, Display, IntoStaticStr)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Hash, Display, IntoStaticStr, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub(crate) enum BreathPhase {
     #[default]
     BreathIn,
@@ -14,9 +52,80 @@ pub(crate) enum BreathPhase {
 }
 impl Eq for BreathPhase {}
 
-pub(crate) const MAX_BREATHE_PHASE_STR_LEN: usize = 8;
+/// Curves mapping the linear progress of a phase, from 0 to 1, to the one shown to the user
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Easing {
+    #[default]
+    Linear,
+    /// slow start, fast end
+    EaseIn,
+    /// fast start, slow end
+    EaseOut,
+}
+
+impl Easing {
+    pub(crate) fn apply(self, progress: f64) -> f64 {
+        let t = progress.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+        }
+    }
+}
 
 impl BreathPhase {
+    /// The easing curve mimicking a natural breath in this phase
+    pub(crate) fn easing(self) -> Easing {
+        match self {
+            BreathPhase::BreathIn => Easing::EaseIn,
+            BreathPhase::BreathOut => Easing::EaseOut,
+            BreathPhase::HoldIn | BreathPhase::HoldOut => Easing::Linear,
+        }
+    }
+
+    /// How full the lungs are, from 0 to 1, when `progress` (from 0 to 1) of the phase is done
+    pub(crate) fn fill(self, progress: f64) -> f64 {
+        let progress = progress.clamp(0.0, 1.0);
+        match self {
+            BreathPhase::BreathIn => progress,
+            BreathPhase::HoldIn => 1.0,
+            BreathPhase::BreathOut => 1.0 - progress,
+            BreathPhase::HoldOut => 0.0,
+        }
+    }
+
+    /// The name used in the configuration file and by scripts, i.e. `breath_in`
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            BreathPhase::BreathIn => "breath_in",
+            BreathPhase::HoldIn => "hold_in",
+            BreathPhase::BreathOut => "breath_out",
+            BreathPhase::HoldOut => "hold_out",
+        }
+    }
+
+    /// The phase with the given `name`
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        [
+            BreathPhase::BreathIn,
+            BreathPhase::HoldIn,
+            BreathPhase::BreathOut,
+            BreathPhase::HoldOut,
+        ]
+        .into_iter()
+        .find(|phase| phase.name() == name)
+    }
+
+    /// A human friendly name of the phase
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            BreathPhase::BreathIn => i18n::t(Message::BreatheIn),
+            BreathPhase::HoldIn | BreathPhase::HoldOut => i18n::t(Message::Hold),
+            BreathPhase::BreathOut => i18n::t(Message::BreatheOut),
+        }
+    }
+
     // Breath phases are ordered. This function returns the next breathing phase
     fn next(self) -> Self {
         match self {
@@ -35,7 +144,41 @@ pub(crate) struct BreathCycle {
     pub(crate) lcm: u64,
 }
 
-fn from_pattern(pattern: &Pattern) -> BreathCycle {
+impl BreathCycle {
+    fn phase_length(&self, phase: BreathPhase) -> u64 {
+        self.cycle.get(&phase).copied().unwrap_or_default()
+    }
+
+    /// The phases longer than 0, in order, with their length
+    pub(crate) fn phases(&self) -> Vec<(BreathPhase, u64)> {
+        let mut phases = Vec::new();
+        let mut phase = BreathPhase::default();
+        loop {
+            let length = self.phase_length(phase);
+            if length != 0 {
+                phases.push((phase, length));
+            }
+            phase = phase.next();
+            if phase == BreathPhase::default() {
+                break;
+            }
+        }
+        phases
+    }
+}
+
+/// The text shown for a phase: the custom label, if any, or the default one
+pub(crate) fn phase_label(labels: &PhaseLabels, phase: BreathPhase) -> &str {
+    let label = match phase {
+        BreathPhase::BreathIn => &labels.breath_in,
+        BreathPhase::HoldIn => &labels.hold_in,
+        BreathPhase::BreathOut => &labels.breath_out,
+        BreathPhase::HoldOut => &labels.hold_out,
+    };
+    label.as_deref().unwrap_or(phase.label())
+}
+
+pub(crate) fn from_pattern(pattern: &Pattern) -> BreathCycle {
     let mut cycle = HashMap::new();
     cycle.insert(BreathPhase::BreathIn, pattern.breath_in);
     cycle.insert(BreathPhase::BreathOut, pattern.breath_out);
@@ -52,62 +195,260 @@ fn from_pattern(pattern: &Pattern) -> BreathCycle {
     }
 }
 
+/// Seconds from `now` to the next occurrence of the wall-clock time `until`
+fn seconds_until(now: chrono::NaiveTime, until: chrono::NaiveTime) -> u64 {
+    let delta = (until - now).num_seconds();
+    if delta > 0 {
+        delta as u64
+    } else {
+        (delta + 24 * 3600) as u64
+    }
+}
+
+/// A phase of the session, `start` is the offset, in seconds, from the beginning of the session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) struct Segment {
+    pub(crate) start: u64,
+    pub(crate) duration: u64,
+    pub(crate) phase: BreathPhase,
+    /// the cycle this segment belongs to, starting from 0
+    pub(crate) cycle: u64,
+}
+
+impl Segment {
+    pub(crate) fn end(&self) -> u64 {
+        self.start + self.duration
+    }
+}
+
+/// The whole session, compiled as a sequence of segments.
+/// The last segment is shorter than its phase if the session ends in the middle of it.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Timeline {
+    /// the phases longer than 0 of a cycle, in order, with their length
+    phases: Vec<(BreathPhase, u64)>,
+    cycle_length: u64,
+    session_length: u64,
+    segments: Vec<Segment>,
+}
+
+impl Timeline {
+    fn new(cycle: &BreathCycle, session_length: u64) -> Self {
+        let phases = cycle.phases();
+        let mut segments = Vec::new();
+        let mut start = 0;
+        'outer: for cycle_index in 0.. {
+            for &(phase, length) in &phases {
+                if start >= session_length {
+                    break 'outer;
+                }
+                let duration = length.min(session_length - start);
+                segments.push(Segment {
+                    start,
+                    duration,
+                    phase,
+                    cycle: cycle_index,
+                });
+                start += duration;
+            }
+        }
+        Timeline {
+            phases,
+            cycle_length: cycle.cycle_length,
+            session_length,
+            segments,
+        }
+    }
+
+    pub(crate) fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// The segment running at the second `t` of the session
+    pub(crate) fn segment_at(&self, t: u64) -> Option<&Segment> {
+        let index = self.segments.partition_point(|segment| segment.end() <= t);
+        self.segments.get(index)
+    }
+
+    /// The number of complete cycles in the session
+    pub(crate) fn full_cycles(&self) -> u64 {
+        self.session_length / self.cycle_length
+    }
+
+    /// The number of cycles in the session, the last one could be incomplete
+    pub(crate) fn cycles(&self) -> u64 {
+        self.segments.last().map_or(0, |segment| segment.cycle + 1)
+    }
+
+    pub(crate) fn session_length(&self) -> u64 {
+        self.session_length
+    }
+
+    pub(crate) fn cycle_length(&self) -> u64 {
+        self.cycle_length
+    }
+
+    /// The full length of a phase, even when its last segment is truncated
+    pub(crate) fn phase_length(&self, phase: BreathPhase) -> u64 {
+        self.phases
+            .iter()
+            .find(|&&(p, _)| p == phase)
+            .map_or(0, |&(_, length)| length)
+    }
+
+    /// The phase at the second `t` and the seconds already spent in it.
+    /// Valid after the end of the session too, as if the cycles continued.
+    fn position_at(&self, t: u64) -> (BreathPhase, u64) {
+        let mut offset = t % self.cycle_length;
+        for &(phase, length) in &self.phases {
+            if offset < length {
+                return (phase, offset);
+            }
+            offset -= length;
+        }
+        unreachable!("the phases add up to the cycle length")
+    }
+}
+
+/// Adjust the session length to the cycle boundaries, as requested by the policy
+fn apply_end_policy(session_length: u64, cycle_length: u64, policy: EndPolicy) -> u64 {
+    let remainder = session_length % cycle_length;
+    if remainder == 0 {
+        return session_length;
+    }
+    let previous_boundary = session_length - remainder;
+    match policy {
+        EndPolicy::Truncate => session_length,
+        EndPolicy::FinishCycle => previous_boundary + cycle_length,
+        EndPolicy::RoundToNearestCycle => {
+            // never round a session down to nothing
+            if remainder * 2 < cycle_length && previous_boundary > 0 {
+                previous_boundary
+            } else {
+                previous_boundary + cycle_length
+            }
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub(crate) struct BreathingSession {
     cycle: BreathCycle,
+    timeline: Timeline,
     pub(crate) session_length: u64,
     total_counter: u64,
     pub(crate) current_state: BreathPhase,
     state_counter: u64,
     state_changed: bool,
+    labels: PhaseLabels,
 }
 
 impl BreathingSession {
-    pub(crate) fn with_opt(opt: &BreathSessionOpt) -> Self {
+    /// Build a session, rejecting patterns that would never make progress
+    pub(crate) fn with_opt(opt: &BreathSessionOpt) -> Result<Self, SessionError> {
+        let pattern_length = opt
+            .pattern
+            .pattern_length
+            .ok_or(SessionError::MissingPatternLength)?;
         let cycle: BreathCycle = from_pattern(opt.pattern);
-        let session_length = match opt.pattern.pattern_length.unwrap() {
+        if cycle.cycle_length == 0 {
+            return Err(SessionError::EmptyCycle);
+        }
+        let session_length = match pattern_length {
             PatternLength::Time(d) => d,
-            PatternLength::Iterations(d) => d * cycle.cycle_length,
+            PatternLength::Iterations(d) => d
+                .checked_mul(cycle.cycle_length)
+                .ok_or(SessionError::TooLong)?,
+            PatternLength::Until(t) => seconds_until(chrono::Local::now().time(), t),
         };
-        BreathingSession {
+        if session_length > MAX_SESSION_LENGTH {
+            return Err(SessionError::TooLong);
+        }
+        let session_length = apply_end_policy(
+            session_length,
+            cycle.cycle_length,
+            opt.pattern.end_policy.unwrap_or_default(),
+        );
+        let timeline = Timeline::new(&cycle, session_length);
+        // the first phase could be 0 seconds long (i.e. 0-4-4-0)
+        let (current_state, _) = timeline.position_at(0);
+        Ok(BreathingSession {
             cycle,
+            timeline,
             session_length,
+            current_state,
+            labels: opt.pattern.labels.clone(),
             ..Default::default()
-        }
+        })
+    }
+
+    pub(crate) fn timeline(&self) -> &Timeline {
+        &self.timeline
+    }
+
+    /// Seconds since the beginning of the session
+    pub(crate) fn elapsed(&self) -> u64 {
+        self.total_counter
+    }
+
+    /// The fraction, from 0 to 1, of the current phase already done,
+    /// `sub_second` (from 0 to 1) being the time passed since the last second
+    pub(crate) fn phase_progress(&self, sub_second: f64) -> f64 {
+        ((self.state_counter as f64 + sub_second) / self.get_current_phase_length() as f64)
+            .clamp(0.0, 1.0)
+    }
+
+    /// Like `phase_progress`, with the easing curve of the current phase applied
+    pub(crate) fn eased_phase_progress(&self, sub_second: f64) -> f64 {
+        self.current_state
+            .easing()
+            .apply(self.phase_progress(sub_second))
+    }
+
+    /// The segment of the timeline running now, None if the session is completed
+    pub(crate) fn current_segment(&self) -> Option<&Segment> {
+        self.timeline.segment_at(self.total_counter)
     }
 
     pub(crate) fn get_current_phase_length(&self) -> u64 {
-        *self.cycle.cycle.get(&self.current_state).unwrap()
+        self.cycle.phase_length(self.current_state)
+    }
+
+    /// The text shown for a phase: the pattern's label, if any, or the default one
+    pub(crate) fn label(&self, phase: BreathPhase) -> &str {
+        phase_label(&self.labels, phase)
+    }
+
+    pub(crate) fn current_label(&self) -> &str {
+        self.label(self.current_state)
     }
 
-    pub(crate) fn phase_as_str(&self) -> &'static str {
-        self.current_state.into()
+    /// The width of the longest label among the phases of the session
+    pub(crate) fn max_label_width(&self) -> usize {
+        self.timeline
+            .phases
+            .iter()
+            .map(|&(phase, _)| console::measure_text_width(self.label(phase)))
+            .max()
+            .unwrap_or_default()
     }
 
     pub(crate) fn get_lengths_lcm(&self) -> u64 {
         self.cycle.lcm
     }
 
-    fn next_state(&mut self) {
-        let mut temp_state = self.current_state.next();
-        while self.cycle.cycle.get(&temp_state).unwrap() == &0 {
-            temp_state = temp_state.next();
-        }
-        self.current_state = temp_state;
-        self.state_counter = 0;
-    }
     pub(crate) fn inc(&mut self) {
         if self.total_counter >= self.session_length {
             return;
         }
         self.total_counter += 1;
-        self.state_counter += 1;
-        if self.state_counter >= *self.cycle.cycle.get(&self.current_state).unwrap() {
-            self.next_state();
-            self.state_changed = true;
-        } else {
-            self.state_changed = false;
-        }
+        let (phase, offset) = match self.timeline.segment_at(self.total_counter) {
+            Some(segment) => (segment.phase, self.total_counter - segment.start),
+            None => self.timeline.position_at(self.total_counter),
+        };
+        self.current_state = phase;
+        self.state_counter = offset;
+        self.state_changed = offset == 0;
     }
     pub(crate) fn is_completed(&self) -> bool {
         self.total_counter >= self.session_length
@@ -116,7 +457,21 @@ impl BreathingSession {
         self.state_changed
     }
     pub(crate) fn print_params(&self) {
-        println!("Session length: {} seconds", self.session_length);
+        let remainder = self.session_length % self.timeline.cycle_length();
+        let remainder = if remainder != 0 {
+            format!(" + {remainder} {}", i18n::t(Message::Seconds))
+        } else {
+            "".to_string()
+        };
+        println!(
+            "{} {} {}, {} {}{}",
+            crate::tui::field_name(Message::Effective),
+            self.session_length,
+            i18n::t(Message::Seconds),
+            self.timeline.full_cycles(),
+            i18n::t(Message::Cycles),
+            remainder
+        );
     }
 }
 
@@ -124,10 +479,105 @@ pub(crate) struct BreathSessionOpt<'a> {
     pub(crate) pattern: &'a Pattern,
 }
 
+/// The whole computed session, meant to be consumed by scripts
+#[derive(Debug, Serialize)]
+pub(crate) struct SessionPlan<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) pattern: &'a Pattern,
+    pub(crate) pattern_length: PatternLength,
+    /// the length of the session in seconds
+    pub(crate) session_length: u64,
+    pub(crate) cycle_length: u64,
+    /// the number of complete cycles in the session
+    pub(crate) cycles: u64,
+    /// the seconds of the last, incomplete, cycle
+    pub(crate) remainder: u64,
+    pub(crate) timeline: Vec<Segment>,
+}
+
+impl<'a> SessionPlan<'a> {
+    pub(crate) fn new(name: &'a str, opt: &BreathSessionOpt<'a>) -> Result<Self, SessionError> {
+        let session = BreathingSession::with_opt(opt)?;
+        let timeline = session.timeline();
+        Ok(SessionPlan {
+            name,
+            pattern: opt.pattern,
+            pattern_length: opt
+                .pattern
+                .pattern_length
+                .ok_or(SessionError::MissingPatternLength)?,
+            session_length: timeline.session_length(),
+            cycle_length: timeline.cycle_length(),
+            cycles: timeline.full_cycles(),
+            remainder: timeline.session_length() % timeline.cycle_length(),
+            timeline: timeline.segments().to_vec(),
+        })
+    }
+}
+
+/// Where a running session is
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Status {
+    pub(crate) pattern: String,
+    pub(crate) paused: bool,
+    /// seconds since the beginning of the session
+    pub(crate) elapsed: u64,
+    pub(crate) session_length: u64,
+    pub(crate) cycles: u64,
+    /// the fields below are missing once the session is completed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cycle: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) phase: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) label: Option<String>,
+    /// seconds spent in the current phase
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) phase_elapsed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) phase_duration: Option<u64>,
+}
+
+impl Status {
+    pub(crate) fn new(pattern: &str, session: &BreathingSession, paused: bool) -> Self {
+        let segment = session.current_segment();
+        Status {
+            pattern: pattern.to_string(),
+            paused,
+            elapsed: session.elapsed(),
+            session_length: session.session_length,
+            cycles: session.timeline().cycles(),
+            cycle: segment.map(|segment| segment.cycle + 1),
+            phase: segment.map(|segment| segment.phase.name().to_string()),
+            label: segment.map(|segment| session.label(segment.phase).to_string()),
+            phase_elapsed: segment.map(|segment| session.elapsed() - segment.start),
+            phase_duration: segment.map(|segment| segment.duration),
+        }
+    }
+}
+
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use super::*;
 
+    /// A 4-2-4 pattern of 2 cycles, for the tests of the other modules too
+    pub(crate) fn pattern() -> Pattern {
+        Pattern {
+            breath_in: 4,
+            hold_in: Some(2),
+            breath_out: 4,
+            pattern_length: Some(PatternLength::Iterations(2)),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn session() -> BreathingSession {
+        BreathingSession::with_opt(&BreathSessionOpt {
+            pattern: &pattern(),
+        })
+        .unwrap()
+    }
+
     #[test]
     fn breath_phase_next() {
         let phase = BreathPhase::BreathIn;
@@ -145,6 +595,7 @@ mod test {
             hold_out: None,
             pattern_length: None,
             description: "Test pattern".to_string(),
+            ..Default::default()
         };
         let got: BreathCycle = from_pattern(&uut);
         assert_eq!(got.cycle.get(&BreathPhase::BreathIn).unwrap(), &4);
@@ -163,6 +614,7 @@ mod test {
             hold_out: None,
             pattern_length: None,
             description: "Test pattern".to_string(),
+            ..Default::default()
         };
         let got: BreathCycle = from_pattern(&uut);
         assert_eq!(got.lcm, 56);
@@ -176,8 +628,9 @@ mod test {
             hold_out: None,
             pattern_length: Some(PatternLength::Time(60)),
             description: "Test pattern".to_string(),
+            ..Default::default()
         };
-        let got = BreathingSession::with_opt(&BreathSessionOpt { pattern });
+        let got = BreathingSession::with_opt(&BreathSessionOpt { pattern }).unwrap();
         assert_eq!(got.cycle.cycle.get(&BreathPhase::BreathIn).unwrap(), &4);
         assert_eq!(got.cycle.cycle.get(&BreathPhase::HoldIn).unwrap(), &7);
         assert_eq!(got.cycle.cycle.get(&BreathPhase::BreathOut).unwrap(), &8);
@@ -193,8 +646,9 @@ mod test {
             hold_out: None,
             pattern_length: Some(PatternLength::Iterations(8)),
             description: "Test pattern".to_string(),
+            ..Default::default()
         };
-        let got = BreathingSession::with_opt(&BreathSessionOpt { pattern });
+        let got = BreathingSession::with_opt(&BreathSessionOpt { pattern }).unwrap();
         assert_eq!(got.cycle.cycle.get(&BreathPhase::BreathIn).unwrap(), &4);
         assert_eq!(got.cycle.cycle.get(&BreathPhase::HoldIn).unwrap(), &7);
         assert_eq!(got.cycle.cycle.get(&BreathPhase::BreathOut).unwrap(), &8);
@@ -210,8 +664,9 @@ mod test {
             hold_out: None,
             pattern_length: Some(PatternLength::Iterations(2)),
             description: "Test pattern".to_string(),
+            ..Default::default()
         };
-        let mut got = BreathingSession::with_opt(&BreathSessionOpt { pattern });
+        let mut got = BreathingSession::with_opt(&BreathSessionOpt { pattern }).unwrap();
         assert_eq!(got.get_current_phase_length(), 4);
         for _ in 0..4 {
             got.inc();
@@ -246,4 +701,276 @@ mod test {
         assert_eq!(got.current_state, BreathPhase::BreathIn);
         assert!(got.is_completed());
     }
+
+    #[test]
+    fn breath_session_ctor_errors() {
+        let pattern = &Pattern {
+            breath_in: 4,
+            hold_in: None,
+            breath_out: 4,
+            hold_out: None,
+            pattern_length: None,
+            description: "Test pattern".to_string(),
+            ..Default::default()
+        };
+        let got = BreathingSession::with_opt(&BreathSessionOpt { pattern });
+        assert_eq!(got.unwrap_err(), SessionError::MissingPatternLength);
+        let pattern = &Pattern {
+            breath_in: 0,
+            hold_in: Some(0),
+            breath_out: 0,
+            hold_out: None,
+            pattern_length: Some(PatternLength::Time(60)),
+            description: "Test pattern".to_string(),
+            ..Default::default()
+        };
+        let got = BreathingSession::with_opt(&BreathSessionOpt { pattern });
+        assert_eq!(got.unwrap_err(), SessionError::EmptyCycle);
+        let pattern = &Pattern {
+            breath_in: 4,
+            breath_out: 4,
+            pattern_length: Some(PatternLength::Iterations(u64::MAX / 2)),
+            description: "Test pattern".to_string(),
+            ..Default::default()
+        };
+        let got = BreathingSession::with_opt(&BreathSessionOpt { pattern });
+        assert_eq!(got.unwrap_err(), SessionError::TooLong);
+        let pattern = &Pattern {
+            pattern_length: Some(PatternLength::Iterations(9_999_999_999_999_999)),
+            ..pattern.clone()
+        };
+        let got = BreathingSession::with_opt(&BreathSessionOpt { pattern });
+        assert_eq!(got.unwrap_err(), SessionError::TooLong);
+        let pattern = &Pattern {
+            pattern_length: Some(PatternLength::Time(MAX_SESSION_LENGTH)),
+            end_policy: Some(EndPolicy::FinishCycle),
+            ..pattern.clone()
+        };
+        assert!(BreathingSession::with_opt(&BreathSessionOpt { pattern }).is_ok());
+    }
+
+    #[test]
+    fn breath_session_zero_length_phases() {
+        let pattern = &Pattern {
+            breath_in: 0,
+            hold_in: Some(2),
+            breath_out: 0,
+            hold_out: None,
+            pattern_length: Some(PatternLength::Iterations(2)),
+            description: "Test pattern".to_string(),
+            ..Default::default()
+        };
+        let mut got = BreathingSession::with_opt(&BreathSessionOpt { pattern }).unwrap();
+        assert_eq!(got.current_state, BreathPhase::HoldIn);
+        assert_eq!(got.get_current_phase_length(), 2);
+        while !got.is_completed() {
+            got.inc();
+            assert_eq!(got.current_state, BreathPhase::HoldIn);
+        }
+        assert_eq!(got.total_counter, 4);
+    }
+
+    #[test]
+    fn breath_session_seconds_until() {
+        let hms = |h, m, s| chrono::NaiveTime::from_hms_opt(h, m, s).unwrap();
+        assert_eq!(seconds_until(hms(7, 0, 0), hms(7, 30, 0)), 1800);
+        assert_eq!(seconds_until(hms(23, 30, 0), hms(0, 15, 0)), 2700);
+        assert_eq!(seconds_until(hms(7, 30, 0), hms(7, 30, 0)), 24 * 3600);
+    }
+
+    #[test]
+    fn session_plan() {
+        let pattern = &Pattern {
+            breath_in: 4,
+            hold_in: Some(7),
+            breath_out: 8,
+            hold_out: None,
+            pattern_length: Some(PatternLength::Time(60)),
+            description: "Test pattern".to_string(),
+            ..Default::default()
+        };
+        let got = SessionPlan::new("test", &BreathSessionOpt { pattern }).unwrap();
+        assert_eq!(got.session_length, 60);
+        assert_eq!(got.cycle_length, 19);
+        assert_eq!(got.cycles, 3);
+        assert_eq!(got.remainder, 3);
+        let segment = |start, duration, phase, cycle| Segment {
+            start,
+            duration,
+            phase,
+            cycle,
+        };
+        assert_eq!(got.timeline.len(), 10);
+        assert_eq!(got.timeline[0], segment(0, 4, BreathPhase::BreathIn, 0));
+        assert_eq!(got.timeline[5], segment(30, 8, BreathPhase::BreathOut, 1));
+        assert_eq!(got.timeline[9], segment(57, 3, BreathPhase::BreathIn, 3));
+        // the same phase names of the control socket and the hooks
+        let json = serde_json::to_value(&got).unwrap();
+        assert_eq!(json["timeline"][5]["phase"], BreathPhase::BreathOut.name());
+    }
+
+    #[test]
+    fn timeline_time_session() {
+        let pattern = &Pattern {
+            breath_in: 4,
+            hold_in: Some(7),
+            breath_out: 8,
+            hold_out: None,
+            pattern_length: Some(PatternLength::Time(300)),
+            description: "Test pattern".to_string(),
+            ..Default::default()
+        };
+        let session = BreathingSession::with_opt(&BreathSessionOpt { pattern }).unwrap();
+        let timeline = session.timeline();
+        assert_eq!(timeline.full_cycles(), 15);
+        assert_eq!(timeline.cycles(), 16);
+        let last = timeline.segments().last().unwrap();
+        // 15 cycles are 285 seconds, the last one is cut after 4+7+4 seconds
+        assert_eq!(last.start, 296);
+        assert_eq!(last.duration, 4);
+        assert_eq!(last.phase, BreathPhase::BreathOut);
+        assert_eq!(last.cycle, 15);
+        assert_eq!(last.end(), 300);
+        let got = timeline.segment_at(137).unwrap();
+        // 137 = 7 * 19 + 4
+        assert_eq!(got.phase, BreathPhase::HoldIn);
+        assert_eq!(got.cycle, 7);
+        assert_eq!(got.start, 137);
+        assert_eq!(
+            timeline.segment_at(136).unwrap().phase,
+            BreathPhase::BreathIn
+        );
+        assert!(timeline.segment_at(300).is_none());
+        // contiguous segments
+        timeline
+            .segments()
+            .windows(2)
+            .for_each(|w| assert_eq!(w[0].end(), w[1].start));
+    }
+
+    #[test]
+    fn timeline_iterations_session() {
+        let pattern = &Pattern {
+            breath_in: 0,
+            hold_in: Some(2),
+            breath_out: 3,
+            hold_out: Some(0),
+            pattern_length: Some(PatternLength::Iterations(3)),
+            description: "Test pattern".to_string(),
+            ..Default::default()
+        };
+        let session = BreathingSession::with_opt(&BreathSessionOpt { pattern }).unwrap();
+        let timeline = session.timeline();
+        assert_eq!(timeline.full_cycles(), 3);
+        assert_eq!(timeline.segments().len(), 6);
+        assert!(timeline
+            .segments()
+            .iter()
+            .all(|segment| segment.phase != BreathPhase::BreathIn));
+        assert_eq!(timeline.segments().last().unwrap().end(), 15);
+    }
+
+    #[test]
+    fn session_end_policy() {
+        assert_eq!(apply_end_policy(300, 19, EndPolicy::Truncate), 300);
+        assert_eq!(apply_end_policy(300, 19, EndPolicy::FinishCycle), 304);
+        assert_eq!(
+            apply_end_policy(300, 19, EndPolicy::RoundToNearestCycle),
+            304
+        );
+        assert_eq!(
+            apply_end_policy(290, 19, EndPolicy::RoundToNearestCycle),
+            285
+        );
+        assert_eq!(apply_end_policy(5, 19, EndPolicy::RoundToNearestCycle), 19);
+        assert_eq!(apply_end_policy(38, 19, EndPolicy::FinishCycle), 38);
+        let pattern = &Pattern {
+            breath_in: 4,
+            hold_in: Some(7),
+            breath_out: 8,
+            hold_out: None,
+            pattern_length: Some(PatternLength::Time(300)),
+            end_policy: Some(EndPolicy::FinishCycle),
+            description: "Test pattern".to_string(),
+            ..Default::default()
+        };
+        let session = BreathingSession::with_opt(&BreathSessionOpt { pattern }).unwrap();
+        assert_eq!(session.session_length, 304);
+        assert_eq!(session.timeline().full_cycles(), 16);
+        assert_eq!(session.timeline().segments().last().unwrap().duration, 8);
+    }
+
+    #[test]
+    fn breath_phase_fill() {
+        assert_eq!(BreathPhase::BreathIn.fill(0.0), 0.0);
+        assert_eq!(BreathPhase::BreathIn.fill(0.25), 0.25);
+        assert_eq!(BreathPhase::HoldIn.fill(0.5), 1.0);
+        assert_eq!(BreathPhase::BreathOut.fill(0.25), 0.75);
+        assert_eq!(BreathPhase::HoldOut.fill(0.5), 0.0);
+        assert_eq!(BreathPhase::BreathIn.fill(2.0), 1.0);
+    }
+
+    #[test]
+    fn easing_curves() {
+        for easing in [Easing::Linear, Easing::EaseIn, Easing::EaseOut] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            assert_eq!(easing.apply(-1.0), 0.0);
+        }
+        assert_eq!(Easing::Linear.apply(0.5), 0.5);
+        assert!(Easing::EaseIn.apply(0.5) < 0.5);
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+    }
+
+    #[test]
+    fn breath_session_phase_progress() {
+        let pattern = &Pattern {
+            breath_in: 4,
+            hold_in: None,
+            breath_out: 4,
+            hold_out: None,
+            pattern_length: Some(PatternLength::Iterations(1)),
+            description: "Test pattern".to_string(),
+            ..Default::default()
+        };
+        let mut got = BreathingSession::with_opt(&BreathSessionOpt { pattern }).unwrap();
+        assert_eq!(got.phase_progress(0.0), 0.0);
+        got.inc();
+        assert_eq!(got.phase_progress(0.0), 0.25);
+        assert_eq!(got.phase_progress(0.5), 0.375);
+        assert_eq!(got.eased_phase_progress(0.0), 0.0625);
+        got.inc();
+        got.inc();
+        got.inc();
+        assert_eq!(got.current_state, BreathPhase::BreathOut);
+        assert_eq!(got.eased_phase_progress(0.0), 0.0);
+        got.inc();
+        assert_eq!(got.eased_phase_progress(0.0), 0.4375);
+    }
+
+    #[test]
+    fn breath_session_labels() {
+        let pattern = &Pattern {
+            breath_in: 4,
+            hold_in: Some(2),
+            breath_out: 4,
+            hold_out: None,
+            pattern_length: Some(PatternLength::Iterations(1)),
+            description: "Test pattern".to_string(),
+            labels: PhaseLabels {
+                breath_out: Some("Exhale slowly with pursed lips".to_string()),
+                hold_out: Some("Never shown, 0 seconds long".repeat(3)),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let got = BreathingSession::with_opt(&BreathSessionOpt { pattern }).unwrap();
+        assert_eq!(got.current_label(), "Breathe in");
+        assert_eq!(got.label(BreathPhase::HoldIn), "Hold");
+        assert_eq!(
+            got.label(BreathPhase::BreathOut),
+            "Exhale slowly with pursed lips"
+        );
+        assert_eq!(got.max_label_width(), 30);
+    }
 }
@@ -1,28 +1,180 @@
+use crate::i18n::Message;
+use crate::sound::SoundConfig;
 use anyhow::{anyhow, Context};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use strum::Display;
+use std::str::FromStr;
 
 const _GLOBAL_CONFIG_DIR_1: &str = "/etc";
 const _GLOBAL_CONFIG_DIR_2: &str = "/usr/local/etc";
 const CONFIG_DEFAULT_NAME: &str = "breathe.toml";
+const SOCKET_DEFAULT_NAME: &str = "breathe.sock";
+const HISTORY_DEFAULT_NAME: &str = "breathe/history.jsonl";
+/// Directory, next to the configuration file, containing additional pattern files
+const PATTERNS_DIR: &str = "breathe/patterns.d";
 
 pub(crate) fn get_default_config_file() -> std::path::PathBuf {
     dirs::config_dir().unwrap().join(CONFIG_DEFAULT_NAME)
 }
 
+/// The control socket of the running session, in the runtime directory if available,
+/// otherwise in a directory of the user in the temporary directory
+pub(crate) fn get_default_socket() -> std::path::PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(|| std::env::temp_dir().join(user_temp_dir_name()))
+        .join(SOCKET_DEFAULT_NAME)
+}
+
+/// The id of the user running breathe
+#[cfg(unix)]
+pub(crate) fn uid() -> u32 {
+    // SAFETY: getuid has no preconditions and never fails
+    unsafe { libc::getuid() }
+}
+
+#[cfg(unix)]
+fn user_temp_dir_name() -> String {
+    format!("breathe-{}", uid())
+}
+
+#[cfg(not(unix))]
+fn user_temp_dir_name() -> String {
+    "breathe".to_string()
+}
+
+/// The log of the practiced sessions, in the data directory
+pub(crate) fn get_default_history_file() -> std::path::PathBuf {
+    dirs::data_dir().unwrap().join(HISTORY_DEFAULT_NAME)
+}
+
 #[derive(Debug, Deserialize)]
 pub(crate) struct Config {
-    patterns: HashMap<String, Pattern>,
+    patterns: HashMap<String, PatternDef>,
+    /// Additional pattern files, relative paths are relative to the configuration file
+    #[serde(default)]
+    include: Vec<std::path::PathBuf>,
+    #[serde(default)]
+    pub(crate) theme: crate::theme::ThemeConfig,
+    #[serde(default)]
+    pub(crate) hooks: crate::hooks::HooksConfig,
+    /// used by `breathe daemon`
+    pub(crate) reminders: Option<crate::daemon::RemindersConfig>,
+    /// labels used by all the patterns, unless overridden by the pattern itself
+    #[serde(default)]
+    labels: PhaseLabels,
+    /// audible cues used by all the patterns, unless overridden by the pattern itself
+    #[serde(default)]
+    sound: SoundConfig,
     #[serde(flatten)]
     pub(crate) pattern_length: PatternLength,
 }
 
+/// A file containing only patterns, loaded from `patterns.d` or via `include`.
+/// Anything else, i.e. a nested `include`, is an error instead of being ignored
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PatternPack {
+    #[serde(default)]
+    patterns: HashMap<String, PatternDef>,
+}
+
+/// A pattern as written in the configuration file.
+/// With `extends`, only the fields different from the parent pattern are needed.
+#[derive(Clone, Debug, Default, Deserialize)]
+struct PatternDef {
+    extends: Option<String>,
+    breath_in: Option<u64>,
+    hold_in: Option<u64>,
+    breath_out: Option<u64>,
+    hold_out: Option<u64>,
+    description: Option<Description>,
+    tags: Option<Vec<String>>,
+    difficulty: Option<Difficulty>,
+    end_policy: Option<EndPolicy>,
+    labels: Option<PhaseLabels>,
+    sound: Option<SoundConfig>,
+    #[serde(flatten)]
+    pattern_length: Option<PatternLength>,
+}
+
+impl PatternDef {
+    /// Fill the fields not specified in this pattern with the ones from the parent
+    fn inherit(&mut self, parent: &PatternDef) {
+        self.breath_in = self.breath_in.or(parent.breath_in);
+        self.hold_in = self.hold_in.or(parent.hold_in);
+        self.breath_out = self.breath_out.or(parent.breath_out);
+        self.hold_out = self.hold_out.or(parent.hold_out);
+        if self.description.is_none() {
+            self.description.clone_from(&parent.description);
+        }
+        if self.tags.is_none() {
+            self.tags.clone_from(&parent.tags);
+        }
+        self.difficulty = self.difficulty.or(parent.difficulty);
+        self.end_policy = self.end_policy.or(parent.end_policy);
+        self.labels = match (self.labels.take(), &parent.labels) {
+            (Some(labels), Some(parent_labels)) => Some(labels.or(parent_labels)),
+            (labels, parent_labels) => labels.or_else(|| parent_labels.clone()),
+        };
+        self.sound = match (self.sound.take(), &parent.sound) {
+            (Some(sound), Some(parent_sound)) => Some(sound.or(parent_sound)),
+            (sound, parent_sound) => sound.or_else(|| parent_sound.clone()),
+        };
+        self.pattern_length = self.pattern_length.or(parent.pattern_length);
+    }
+
+    fn into_pattern(self, pattern_name: &str) -> anyhow::Result<Pattern> {
+        let missing = |field: &str| anyhow!("Pattern {pattern_name}: missing field {field}");
+        Ok(Pattern {
+            breath_in: self.breath_in.ok_or_else(|| missing("breath_in"))?,
+            hold_in: self.hold_in,
+            breath_out: self.breath_out.ok_or_else(|| missing("breath_out"))?,
+            hold_out: self.hold_out,
+            description: self
+                .description
+                .ok_or_else(|| missing("description"))?
+                .resolve(crate::i18n::locale()),
+            tags: self.tags.unwrap_or_default(),
+            difficulty: self.difficulty,
+            end_policy: self.end_policy,
+            labels: self.labels.unwrap_or_default(),
+            sound: self.sound.unwrap_or_default(),
+            pattern_length: self.pattern_length,
+        })
+    }
+}
+
+/// A pattern description, either a plain text or a text for each language:
+/// `description.en = "..."`, `description.it = "..."`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum Description {
+    Text(String),
+    Localized(std::collections::BTreeMap<String, String>),
+}
+
+impl Description {
+    /// The text in the given language, falling back to english and then to any language
+    fn resolve(self, locale: crate::i18n::Locale) -> String {
+        match self {
+            Description::Text(text) => text,
+            Description::Localized(mut texts) => texts
+                .remove(locale.code())
+                .or_else(|| texts.remove(crate::i18n::Locale::En.code()))
+                .or_else(|| texts.into_values().next())
+                .unwrap_or_default(),
+        }
+    }
+}
+
 pub(crate) fn from_file(config_file: &std::path::Path) -> anyhow::Result<Config> {
     if config_file.exists() && config_file.is_file() {
         let temp_str = std::fs::read_to_string(config_file)
             .with_context(|| format!("Failed to read config from {}", config_file.display()))?;
-        let conf: Config = toml::from_str(&temp_str)
+        let mut conf: Config = toml::from_str(&temp_str)
+            .with_context(|| format!("Invalid configuration in {}", config_file.display()))?;
+        conf.load_packs(config_file)?;
+        conf.validate()
             .with_context(|| format!("Invalid configuration in {}", config_file.display()))?;
         Ok(conf)
     } else {
@@ -34,46 +186,437 @@ pub(crate) fn from_file(config_file: &std::path::Path) -> anyhow::Result<Config>
 }
 
 impl Config {
+    /// A configuration without patterns, when the configuration file is optional
+    pub(crate) fn empty() -> Self {
+        Config {
+            patterns: HashMap::new(),
+            include: Vec::new(),
+            theme: Default::default(),
+            hooks: Default::default(),
+            reminders: None,
+            labels: Default::default(),
+            sound: Default::default(),
+            pattern_length: PatternLength::Iterations(1),
+        }
+    }
+}
+
+/// List the pattern files to be loaded, `patterns.d` first, then the included ones
+fn get_pack_files(
+    config_file: &std::path::Path,
+    include: &[std::path::PathBuf],
+) -> anyhow::Result<Vec<std::path::PathBuf>> {
+    let base_dir = config_file.parent().unwrap_or(std::path::Path::new("."));
+    let patterns_dir = base_dir.join(PATTERNS_DIR);
+    let mut result = Vec::new();
+    if patterns_dir.is_dir() {
+        for entry in std::fs::read_dir(&patterns_dir)
+            .with_context(|| format!("Failed to read {}", patterns_dir.display()))?
+        {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "toml") {
+                result.push(path);
+            }
+        }
+        result.sort();
+    }
+    result.extend(include.iter().map(|path| base_dir.join(path)));
+    Ok(result)
+}
+
+impl Config {
+    /// Merge the patterns defined in other files.
+    /// On name collisions, the pattern is renamed as `file_name/pattern_name`,
+    /// and so are the `extends` of the same file targeting it
+    fn load_packs(&mut self, config_file: &std::path::Path) -> anyhow::Result<()> {
+        for pack_file in get_pack_files(config_file, &self.include)? {
+            let temp_str = std::fs::read_to_string(&pack_file)
+                .with_context(|| format!("Failed to read patterns from {}", pack_file.display()))?;
+            let pack: PatternPack = toml::from_str(&temp_str)
+                .with_context(|| format!("Invalid patterns in {}", pack_file.display()))?;
+            let pack_name = pack_file
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let renamed: HashMap<String, String> = pack
+                .patterns
+                .keys()
+                .filter(|name| self.patterns.contains_key(*name))
+                .map(|name| (name.clone(), format!("{pack_name}/{name}")))
+                .collect();
+            for (name, mut def) in pack.patterns {
+                if let Some(parent) = def.extends.as_ref().and_then(|parent| renamed.get(parent)) {
+                    def.extends = Some(parent.clone());
+                }
+                match renamed.get(&name) {
+                    Some(new_name) => {
+                        log::warn!(
+                            "Pattern {name} from {} already defined, renamed as {new_name}",
+                            pack_file.display()
+                        );
+                        if self.patterns.insert(new_name.clone(), def).is_some() {
+                            return Err(anyhow!(
+                                "Pattern {new_name} defined more than once in {}",
+                                pack_file.display()
+                            ));
+                        }
+                    }
+                    None => {
+                        self.patterns.insert(name, def);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn compute_pattern(
         &self,
         pattern_name: &str,
         opt_pattern_length: Option<PatternLength>,
     ) -> anyhow::Result<Pattern> {
+        let result = self.resolve_pattern(pattern_name)?;
+        Ok(self.complete_pattern(result, opt_pattern_length))
+    }
+
+    /// Follow the `extends` chain of a pattern, returning its effective values
+    fn resolve_pattern(&self, pattern_name: &str) -> anyhow::Result<Pattern> {
         let mut result = self
             .patterns
             .get(pattern_name)
             .with_context(|| format!("Pattern {pattern_name} not found"))?
             .clone();
-        result.pattern_length = Some(
-            opt_pattern_length.unwrap_or(result.pattern_length.unwrap_or(self.pattern_length)),
+        let mut chain = vec![pattern_name];
+        let mut parent_name = result.extends.clone();
+        while let Some(name) = parent_name {
+            if chain.contains(&name.as_str()) {
+                return Err(anyhow!(
+                    "Pattern {pattern_name}: inheritance cycle {} -> {name}",
+                    chain.join(" -> ")
+                ));
+            }
+            let (name, parent) = self.patterns.get_key_value(&name).with_context(|| {
+                format!("Pattern {pattern_name}: parent pattern {name} not found")
+            })?;
+            result.inherit(parent);
+            chain.push(name);
+            parent_name = parent.extends.clone();
+        }
+        result.into_pattern(pattern_name)
+    }
+
+    /// Check that every pattern can be resolved
+    fn validate(&self) -> anyhow::Result<()> {
+        self.patterns
+            .keys()
+            .try_for_each(|name| self.resolve_pattern(name).map(|_| ()))
+    }
+
+    /// Fill the missing session length of a pattern, i.e. one built from the command line
+    pub(crate) fn complete_pattern(
+        &self,
+        mut pattern: Pattern,
+        opt_pattern_length: Option<PatternLength>,
+    ) -> Pattern {
+        pattern.pattern_length = Some(
+            opt_pattern_length.unwrap_or(pattern.pattern_length.unwrap_or(self.pattern_length)),
         );
-        Ok(result)
+        pattern.labels = pattern.labels.or(&self.labels);
+        pattern.sound = pattern.sound.or(&self.sound);
+        pattern
     }
 
-    pub(crate) fn print_pattern_list(&self) {
-        self.patterns.iter().for_each(|(name, pattern)| {
-            println!(
-                "{} [{}] [{}]: {}",
-                name,
-                pattern.get_short_string(),
-                pattern.get_short_session_string(),
-                pattern.description
-            )
-        })
+    pub(crate) fn has_pattern(&self, pattern_name: &str) -> bool {
+        self.patterns.contains_key(pattern_name)
+    }
+
+    /// The patterns matching the filter, with their effective values, sorted by name
+    fn filter_patterns(&self, filter: &PatternFilter) -> Vec<PatternEntry<'_>> {
+        let mut names: Vec<&String> = self.patterns.keys().collect();
+        names.sort_by_key(|name| name.to_lowercase());
+        names
+            .into_iter()
+            .filter_map(|name| match self.resolve_pattern(name) {
+                Ok(pattern) => Some(PatternEntry {
+                    name,
+                    extends: self.patterns[name].extends.as_deref(),
+                    pattern,
+                }),
+                Err(e) => {
+                    log::warn!("{e}");
+                    None
+                }
+            })
+            .filter(|entry| filter.matches(entry.name, &entry.pattern))
+            .collect()
+    }
+
+    /// The patterns matching the filter, sorted by name, as a JSON array
+    pub(crate) fn pattern_list_json(&self, filter: &PatternFilter) -> anyhow::Result<String> {
+        Ok(serde_json::to_string(&self.filter_patterns(filter))?)
+    }
+
+    /// Print the patterns matching the filter, sorted by name
+    pub(crate) fn print_pattern_list(
+        &self,
+        filter: &PatternFilter,
+        view: ListView,
+        format: OutputFormat,
+    ) -> anyhow::Result<()> {
+        let entries = self.filter_patterns(filter);
+        match format {
+            OutputFormat::Table => entries
+                .iter()
+                .for_each(|entry| entry.pattern.print(entry.name, entry.extends, view)),
+            OutputFormat::Json => {
+                crate::write_output(&format!("{}\n", serde_json::to_string_pretty(&entries)?))?
+            }
+            OutputFormat::Toml => {
+                let patterns: std::collections::BTreeMap<&str, &Pattern> = entries
+                    .iter()
+                    .map(|entry| (entry.name, &entry.pattern))
+                    .collect();
+                crate::write_output(
+                    &toml::to_string(&PatternList { patterns })
+                        .context("Failed to serialize the patterns")?,
+                )?;
+            }
+        }
+        Ok(())
     }
 }
 
-#[derive(Clone, Debug, Deserialize)]
+/// A named pattern, as shown by `print_pattern_list`
+#[derive(Debug, Serialize)]
+struct PatternEntry<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extends: Option<&'a str>,
+    #[serde(flatten)]
+    pattern: Pattern,
+}
+
+/// The toml output uses the same layout of the configuration file
+#[derive(Debug, Serialize)]
+struct PatternList<'a> {
+    patterns: std::collections::BTreeMap<&'a str, &'a Pattern>,
+}
+
+/// Output format of the commands printing patterns or sessions
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// human readable text
+    #[default]
+    Table,
+    Json,
+    Toml,
+}
+
+/// How much information `print_pattern_list` shows for each pattern
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum ListView {
+    /// name, pattern and session length
+    Compact,
+    /// one line per pattern, with description and tags
+    #[default]
+    Normal,
+    /// all the fields, one per line
+    Detailed,
+}
+
+/// Criteria to select patterns; empty fields match everything
+#[derive(Debug, Default, Clone)]
+pub(crate) struct PatternFilter {
+    pub(crate) tags: Vec<String>,
+    pub(crate) difficulty: Option<Difficulty>,
+    pub(crate) text: Option<String>,
+}
+
+impl PatternFilter {
+    fn matches(&self, name: &str, pattern: &Pattern) -> bool {
+        let tags_match = self.tags.iter().all(|tag| {
+            pattern
+                .tags
+                .iter()
+                .any(|pattern_tag| pattern_tag.eq_ignore_ascii_case(tag))
+        });
+        let difficulty_match = self.difficulty.is_none() || self.difficulty == pattern.difficulty;
+        let text_match = self.text.as_ref().is_none_or(|text| {
+            let text = text.to_lowercase();
+            name.to_lowercase().contains(&text)
+                || pattern.description.to_lowercase().contains(&text)
+        });
+        tags_match && difficulty_match && text_match
+    }
+}
+
+/// Append a new pattern to the configuration file, leaving the rest of the file untouched
+pub(crate) fn save_pattern(
+    config_file: &std::path::Path,
+    pattern_name: &str,
+    pattern: &Pattern,
+) -> anyhow::Result<()> {
+    if pattern_name.is_empty()
+        || !pattern_name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Err(anyhow!(
+            "Invalid pattern name {pattern_name}: only letters, digits, '_' and '-' are allowed"
+        ));
+    }
+    let config = from_file(config_file)?;
+    if config.has_pattern(pattern_name) {
+        return Err(anyhow!(
+            "Pattern {pattern_name} already exists in {}",
+            config_file.display()
+        ));
+    }
+    let body = toml::to_string(pattern)
+        .with_context(|| format!("Failed to serialize pattern {pattern_name}"))?;
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(config_file)
+        .with_context(|| format!("Failed to open {} for writing", config_file.display()))?;
+    use std::io::Write;
+    write!(file, "\n[patterns.{pattern_name}]\n{body}")
+        .with_context(|| format!("Failed to write pattern to {}", config_file.display()))?;
+    Ok(())
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, strum::Display, clap::ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum Difficulty {
+    Beginner,
+    Intermediate,
+    Advanced,
+}
+
+/// How a time based session ends, when its length is not a multiple of the cycle length
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    strum::Display,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub(crate) enum EndPolicy {
+    /// stop as soon as the time is over, even in the middle of a cycle
+    #[default]
+    Truncate,
+    /// complete the last cycle
+    FinishCycle,
+    /// stop at the cycle boundary closest to the session length
+    RoundToNearestCycle,
+}
+
+/// Custom text shown for each phase, i.e. "Inhale through the nose"
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct PhaseLabels {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) breath_in: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) hold_in: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) breath_out: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) hold_out: Option<String>,
+}
+
+impl PhaseLabels {
+    /// Fill the labels not specified here with the ones in `fallback`
+    pub(crate) fn or(mut self, fallback: &PhaseLabels) -> Self {
+        for (label, fallback) in [
+            (&mut self.breath_in, &fallback.breath_in),
+            (&mut self.hold_in, &fallback.hold_in),
+            (&mut self.breath_out, &fallback.breath_out),
+            (&mut self.hold_out, &fallback.hold_out),
+        ] {
+            if label.is_none() {
+                label.clone_from(fallback);
+            }
+        }
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == PhaseLabels::default()
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub(crate) struct Pattern {
     pub(crate) breath_in: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) hold_in: Option<u64>,
     pub(crate) breath_out: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) hold_out: Option<u64>,
     pub(crate) description: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) difficulty: Option<Difficulty>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) end_policy: Option<EndPolicy>,
+    #[serde(default, skip_serializing_if = "PhaseLabels::is_empty")]
+    pub(crate) labels: PhaseLabels,
+    #[serde(default, skip_serializing_if = "SoundConfig::is_empty")]
+    pub(crate) sound: SoundConfig,
     #[serde(flatten)]
     pub(crate) pattern_length: Option<PatternLength>,
 }
 
+const CUSTOM_PATTERN_DESCRIPTION: &str = "Custom pattern";
+
+/// The fields of the detailed pattern list
+const DETAIL_FIELDS: [Message; 7] = [
+    Message::Pattern,
+    Message::SessionLength,
+    Message::Extends,
+    Message::EndPolicy,
+    Message::Difficulty,
+    Message::Tags,
+    Message::Description,
+];
+
+/// Parse a pattern in the same form printed by `get_short_string`, i.e. 4-7-8-0
+impl FromStr for Pattern {
+    type Err = anyhow::Error;
+
+    fn from_str(src: &str) -> anyhow::Result<Self> {
+        let v = src
+            .trim()
+            .split('-')
+            .map(|x| u64::from_str(x.trim()).with_context(|| format!("Invalid phase length {x}")))
+            .collect::<anyhow::Result<Vec<u64>>>()?;
+        if v.len() != 4 {
+            return Err(anyhow!(
+                "Invalid pattern {src}: expected 4 phases in the form breath_in-hold_in-breath_out-hold_out"
+            ));
+        }
+        let non_zero = |x: u64| if x == 0 { None } else { Some(x) };
+        Ok(Pattern {
+            breath_in: v[0],
+            hold_in: non_zero(v[1]),
+            breath_out: v[2],
+            hold_out: non_zero(v[3]),
+            description: CUSTOM_PATTERN_DESCRIPTION.to_string(),
+            ..Default::default()
+        })
+    }
+}
+
 impl Pattern {
     pub(crate) fn length(&self) -> u64 {
         self.breath_in
@@ -91,55 +634,250 @@ impl Pattern {
             self.hold_out.unwrap_or(0)
         )
     }
-    fn get_short_session_string(&self) -> String {
-        if let Some(pl) = self.pattern_length {
-            match pl {
-                PatternLength::Time(d) => format!("{d} seconds"),
-                PatternLength::Iterations(d) => format!("{d} iterations"),
+    fn print(&self, name: &str, extends: Option<&str>, view: ListView) {
+        match view {
+            ListView::Compact => println!(
+                "{} [{}] [{}]",
+                name,
+                self.get_short_string(),
+                self.get_short_session_string()
+            ),
+            ListView::Normal => {
+                let extends = extends
+                    .map(|parent| {
+                        format!(
+                            " ({} {parent})",
+                            crate::i18n::t(Message::Extends).to_lowercase()
+                        )
+                    })
+                    .unwrap_or_default();
+                let tags = if self.tags.is_empty() {
+                    "".to_string()
+                } else {
+                    format!(" #{}", self.tags.join(" #"))
+                };
+                println!(
+                    "{} [{}] [{}]{}: {}{}",
+                    name,
+                    self.get_short_string(),
+                    self.get_short_session_string(),
+                    extends,
+                    self.description,
+                    tags
+                )
+            }
+            ListView::Detailed => {
+                let field = |message| crate::tui::padded_field_name(message, &DETAIL_FIELDS);
+                println!("{name}");
+                println!("  {} {}", field(Message::Pattern), self.get_short_string());
+                if let Some(pl) = self.pattern_length {
+                    println!("  {} {pl}", field(Message::SessionLength));
+                }
+                if let Some(parent) = extends {
+                    println!("  {} {parent}", field(Message::Extends));
+                }
+                if let Some(end_policy) = self.end_policy {
+                    println!("  {} {end_policy}", field(Message::EndPolicy));
+                }
+                if let Some(difficulty) = self.difficulty {
+                    println!("  {} {difficulty}", field(Message::Difficulty));
+                }
+                if !self.tags.is_empty() {
+                    println!("  {} {}", field(Message::Tags), self.tags.join(", "));
+                }
+                println!("  {} {}", field(Message::Description), self.description);
             }
-        } else {
-            "".to_string()
         }
     }
-}
 
-use std::str::FromStr;
+    fn get_short_session_string(&self) -> String {
+        self.pattern_length
+            .map(|pl| pl.to_string())
+            .unwrap_or_default()
+    }
+}
 
-#[derive(Debug, Clone, Display, Deserialize, PartialEq, Copy)]
-#[strum(ascii_case_insensitive)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Copy)]
+#[serde(rename_all = "snake_case")]
 pub(crate) enum PatternLength {
-    #[strum(to_string = "Time={0}")]
-    #[serde(alias = "time")]
+    #[serde(alias = "Time", deserialize_with = "de_time_length")]
     Time(u64),
-    #[strum(to_string = "Iterations={0}")]
-    #[serde(alias = "iteration", alias = "Iteration", alias = "iterations")]
+    #[serde(
+        alias = "iteration",
+        alias = "Iteration",
+        alias = "Iterations",
+        deserialize_with = "de_iterations"
+    )]
     Iterations(u64),
+    /// Run the session until a wall-clock time
+    #[serde(
+        alias = "Until",
+        deserialize_with = "de_until",
+        serialize_with = "ser_until"
+    )]
+    Until(chrono::NaiveTime),
+}
+
+const ITERATIONS_SUFFIXES: [&str; 5] = ["iterations", "iteration", "rounds", "round", "x"];
+
+/// Parse a time length like 300, 300s, 20m or 1h30m into seconds, longer than 0
+pub(crate) fn parse_time_length(src: &str) -> anyhow::Result<u64> {
+    if src.is_empty() {
+        return Err(anyhow!("Invalid time length: empty value"));
+    }
+    let total = match u64::from_str(src) {
+        Ok(d) => d,
+        Err(_) => parse_time_units(src)?,
+    };
+    if total == 0 {
+        return Err(anyhow!("Invalid time length {src}: must be longer than 0"));
+    }
+    Ok(total)
+}
+
+fn parse_time_units(src: &str) -> anyhow::Result<u64> {
+    let mut total = 0u64;
+    let mut number = String::new();
+    for c in src.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let multiplier = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(anyhow!("Invalid time unit '{c}' in {src}")),
+        };
+        let value = u64::from_str(&number)
+            .with_context(|| format!("Invalid time length {src}: missing number before '{c}'"))?;
+        total = value
+            .checked_mul(multiplier)
+            .and_then(|seconds| total.checked_add(seconds))
+            .ok_or_else(|| anyhow!("Invalid time length {src}: too long"))?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(anyhow!(
+            "Invalid time length {src}: missing unit after {number}"
+        ));
+    }
+    Ok(total)
+}
+
+/// Parse a number of iterations like 10, 10x or 10rounds, more than 0
+fn parse_iterations(src: &str) -> anyhow::Result<u64> {
+    let number = ITERATIONS_SUFFIXES
+        .iter()
+        .find_map(|suffix| src.strip_suffix(suffix))
+        .unwrap_or(src);
+    match u64::from_str(number).with_context(|| format!("Invalid number of iterations {src}"))? {
+        0 => Err(anyhow!(
+            "Invalid number of iterations {src}: must be more than 0"
+        )),
+        iterations => Ok(iterations),
+    }
+}
+
+/// Parse a wall-clock time like 07:30 or 07:30:15
+pub(crate) fn parse_until(src: &str) -> anyhow::Result<chrono::NaiveTime> {
+    chrono::NaiveTime::parse_from_str(src, "%H:%M")
+        .or_else(|_| chrono::NaiveTime::parse_from_str(src, "%H:%M:%S"))
+        .with_context(|| format!("Invalid wall-clock time {src}, expected HH:MM"))
+}
+
+/// Lowercase and without spaces, i.e. "1h 30M" becomes "1h30m"
+pub(crate) fn normalize(src: &str) -> String {
+    let mut src = src.to_ascii_lowercase();
+    src.retain(|c| !c.is_whitespace());
+    src
 }
 
+/// TOML values can be both integers (seconds, iterations) and friendly strings
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LengthValue {
+    Number(u64),
+    Text(String),
+}
+
+fn de_time_length<'de, D: serde::Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+    let src = match LengthValue::deserialize(d)? {
+        LengthValue::Number(n) => n.to_string(),
+        LengthValue::Text(s) => normalize(&s),
+    };
+    parse_time_length(&src).map_err(serde::de::Error::custom)
+}
+
+fn de_iterations<'de, D: serde::Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+    let src = match LengthValue::deserialize(d)? {
+        LengthValue::Number(n) => n.to_string(),
+        LengthValue::Text(s) => normalize(&s),
+    };
+    parse_iterations(&src).map_err(serde::de::Error::custom)
+}
+
+fn de_until<'de, D: serde::Deserializer<'de>>(d: D) -> Result<chrono::NaiveTime, D::Error> {
+    let s = String::deserialize(d)?;
+    parse_until(s.trim()).map_err(serde::de::Error::custom)
+}
+
+fn ser_until<S: serde::Serializer>(t: &chrono::NaiveTime, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&t.format("%H:%M").to_string())
+}
+
+/// Accepted forms:
+/// - time=300, iterations=8, until=07:30 (time accepts friendly lengths too, i.e. time=20m)
+/// - 20m, 1h30m, 45s
+/// - 10x, 10 rounds, 10 iterations
 impl FromStr for PatternLength {
     type Err = anyhow::Error;
 
     fn from_str(src: &str) -> anyhow::Result<Self> {
-        let mut src = src.to_string();
-        src.retain(|c| c != ' ');
-        let v: Vec<&str> = src.trim().split('=').collect();
-        if v.len() != 2 {
-            return Err(anyhow!(
-                "Invalid pattern length specification: no '=' found in {}",
-                src
-            ));
+        let src = normalize(src);
+        if let Some((kind, value)) = src.split_once('=') {
+            return match kind {
+                "time" => Ok(PatternLength::Time(parse_time_length(value)?)),
+                "iterations" | "iteration" => {
+                    Ok(PatternLength::Iterations(parse_iterations(value)?))
+                }
+                "until" => Ok(PatternLength::Until(parse_until(value)?)),
+                _ => Err(anyhow!("Duration type {} not recognized", kind)),
+            };
+        }
+        if ITERATIONS_SUFFIXES
+            .iter()
+            .any(|suffix| src.len() > suffix.len() && src.ends_with(suffix))
+        {
+            Ok(PatternLength::Iterations(parse_iterations(&src)?))
+        } else if u64::from_str(&src).is_ok() {
+            Err(anyhow!(
+                "Ambiguous pattern length {src}: use a unit, like {src}s or {src}x"
+            ))
+        } else {
+            Ok(PatternLength::Time(parse_time_length(&src)?))
         }
-        let duration = u64::from_str(v.get(1).unwrap())
-            .with_context(|| format!("Invalid duration {}", v.get(1).unwrap()))?;
-        match *v.first().unwrap() {
-            "time" | "Time" => Ok(PatternLength::Time(duration)),
-            "iterations" | "Iterations" | "iteration" | "Iteration" => {
-                Ok(PatternLength::Iterations(duration))
+    }
+}
+
+impl std::fmt::Display for PatternLength {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatternLength::Time(d) => {
+                let (h, m, s) = (d / 3600, d % 3600 / 60, d % 60);
+                if h > 0 {
+                    write!(f, "{h}h")?;
+                }
+                if m > 0 {
+                    write!(f, "{m}m")?;
+                }
+                if s > 0 || *d == 0 {
+                    write!(f, "{s}s")?;
+                }
+                Ok(())
             }
-            _ => Err(anyhow!(
-                "Duration type {} not recognized",
-                v.first().unwrap()
-            )),
+            PatternLength::Iterations(d) => write!(f, "{d} rounds"),
+            PatternLength::Until(t) => write!(f, "until={}", t.format("%H:%M")),
         }
     }
 }
@@ -152,13 +890,6 @@ impl PatternLength {
     fn _is_time(&self) -> bool {
         matches!(self, PatternLength::Time(_))
     }
-
-    fn _duration(&self) -> u64 {
-        match self {
-            PatternLength::Time(d) => *d,
-            PatternLength::Iterations(d) => *d,
-        }
-    }
 }
 
 #[cfg(test)]
@@ -253,6 +984,364 @@ mod test {
         pl_parse_test("Iterations=5", PatternLength::Iterations(5));
         pl_parse_test("iterations = 5", PatternLength::Iterations(5));
         pl_parse_test("iteration = 123", PatternLength::Iterations(123));
-        pl_parse_test("time = 20", PatternLength::Time(20))
+        pl_parse_test("time = 20", PatternLength::Time(20));
+        // toml only, friendly strings
+        let toml_parse = |s: &str| toml::from_str::<PatternLength>(s).unwrap();
+        assert_eq!(toml_parse("time = \"20m\""), PatternLength::Time(1200));
+        assert_eq!(
+            toml_parse("iterations = \"10x\""),
+            PatternLength::Iterations(10)
+        );
+        assert_eq!(
+            toml_parse("until = \"07:30\""),
+            PatternLength::Until(chrono::NaiveTime::from_hms_opt(7, 30, 0).unwrap())
+        );
+        // the capitalized names of the older versions
+        assert_eq!(toml_parse("Time = 20"), PatternLength::Time(20));
+        assert_eq!(
+            toml_parse("Until = \"07:30\""),
+            PatternLength::Until(chrono::NaiveTime::from_hms_opt(7, 30, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn pattern_length_friendly() {
+        let parse = |s: &str| PatternLength::from_str(s).inspect_err(|e| eprintln!("{:?}", e));
+        assert_eq!(parse("20m").unwrap(), PatternLength::Time(1200));
+        assert_eq!(parse("1h30m").unwrap(), PatternLength::Time(5400));
+        assert_eq!(parse("45s").unwrap(), PatternLength::Time(45));
+        assert_eq!(parse("10x").unwrap(), PatternLength::Iterations(10));
+        assert_eq!(parse("10 rounds").unwrap(), PatternLength::Iterations(10));
+        assert_eq!(
+            parse("until=07:30").unwrap(),
+            PatternLength::Until(chrono::NaiveTime::from_hms_opt(7, 30, 0).unwrap())
+        );
+        assert!(parse("10").is_err());
+        assert!(parse("1h30").is_err());
+        assert!(parse("10y").is_err());
+        assert!(parse("until=25:00").is_err());
+        assert!(parse("x").is_err());
+        assert!(parse("time=").is_err());
+        assert!(parse("9999999999999999h").is_err());
+        assert!(parse("time=18446744073709551615s1s").is_err());
+        // empty sessions
+        for zero in ["0s", "0m", "0h0m", "0x", "time=0", "iterations=0"] {
+            assert!(parse(zero).is_err(), "{zero}");
+        }
+        assert!(toml::from_str::<PatternLength>("time = 0").is_err());
+    }
+
+    #[test]
+    fn pattern_length_display_roundtrip() {
+        for pl in [
+            PatternLength::Time(1200),
+            PatternLength::Time(5400),
+            PatternLength::Time(3725),
+            PatternLength::Iterations(8),
+            PatternLength::Until(chrono::NaiveTime::from_hms_opt(7, 30, 0).unwrap()),
+        ] {
+            assert_eq!(PatternLength::from_str(&pl.to_string()).unwrap(), pl);
+        }
+        assert_eq!(PatternLength::Time(1200).to_string(), "20m");
+        assert_eq!(PatternLength::Time(5400).to_string(), "1h30m");
+        assert_eq!(PatternLength::Iterations(10).to_string(), "10 rounds");
+    }
+
+    #[test]
+    fn pattern_from_str() {
+        let got = Pattern::from_str("4-7-8-0");
+        assert!(got.is_ok());
+        let got = got.unwrap();
+        assert_eq!(got.breath_in, 4);
+        assert_eq!(got.hold_in, Some(7));
+        assert_eq!(got.breath_out, 8);
+        assert_eq!(got.hold_out, None);
+        assert_eq!(got.get_short_string(), "4-7-8-0");
+        assert!(Pattern::from_str("4-7-8").is_err());
+        assert!(Pattern::from_str("4-7-8-0-1").is_err());
+        assert!(Pattern::from_str("4-a-8-0").is_err());
+    }
+
+    #[test]
+    fn save_pattern_roundtrip() {
+        let config_file = std::env::temp_dir().join(format!("breathe-{}.toml", std::process::id()));
+        std::fs::copy("resources/tests/config.toml", &config_file).unwrap();
+        let mut pattern = Pattern::from_str("5-0-5-0").unwrap();
+        pattern.description = "A \"quoted\" description".to_string();
+        pattern.pattern_length = Some(PatternLength::Time(120));
+        let result = save_pattern(&config_file, "coherent", &pattern);
+        assert!(result.inspect_err(|e| eprintln!("{:?}", e)).is_ok());
+        assert!(save_pattern(&config_file, "coherent", &pattern).is_err());
+        assert!(save_pattern(&config_file, "not valid", &pattern).is_err());
+        let config = from_file(&config_file);
+        std::fs::remove_file(&config_file).unwrap();
+        let got = config.unwrap().compute_pattern("coherent", None).unwrap();
+        assert_eq!(got.get_short_string(), "5-0-5-0");
+        assert_eq!(got.description, pattern.description);
+        assert_eq!(got.pattern_length, Some(PatternLength::Time(120)));
+    }
+
+    fn config_from_str(input: &str) -> anyhow::Result<Config> {
+        let config: Config = toml::from_str(input)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[test]
+    fn pattern_inheritance() {
+        let config = get_standard_config();
+        let got = config.compute_pattern("relax_short", None).unwrap();
+        assert_eq!(got.get_short_string(), "4-7-8-0");
+        assert_eq!(got.pattern_length, Some(PatternLength::Iterations(4)));
+        let config = config_from_str(
+            r#"
+            time = 60
+            [patterns.base]
+            breath_in = 4
+            breath_out = 4
+            description = "base"
+            iterations = 4
+            [patterns.middle]
+            extends = "base"
+            hold_in = 2
+            [patterns.leaf]
+            extends = "middle"
+            breath_out = 6
+            description = "leaf"
+            "#,
+        )
+        .unwrap();
+        let got = config.compute_pattern("leaf", None).unwrap();
+        assert_eq!(got.get_short_string(), "4-2-6-0");
+        assert_eq!(got.description, "leaf");
+        assert_eq!(got.pattern_length, Some(PatternLength::Iterations(4)));
+    }
+
+    #[test]
+    fn pattern_inheritance_errors() {
+        let cycle = config_from_str(
+            r#"
+            time = 60
+            [patterns.a]
+            extends = "b"
+            [patterns.b]
+            extends = "a"
+            "#,
+        );
+        assert!(cycle.is_err());
+        let self_cycle = config_from_str(
+            r#"
+            time = 60
+            [patterns.a]
+            extends = "a"
+            breath_in = 4
+            breath_out = 4
+            description = "a"
+            "#,
+        );
+        assert!(self_cycle.is_err());
+        let missing_parent = config_from_str(
+            r#"
+            time = 60
+            [patterns.a]
+            extends = "nope"
+            "#,
+        );
+        assert!(missing_parent.is_err());
+        let missing_field = config_from_str(
+            r#"
+            time = 60
+            [patterns.a]
+            breath_in = 4
+            description = "a"
+            "#,
+        );
+        assert!(missing_field.is_err());
+    }
+
+    #[test]
+    fn config_pattern_packs() {
+        let config = from_file(Path::new("resources/tests/packs/breathe.toml"))
+            .inspect_err(|e| eprintln!("{:?}", e));
+        assert!(config.is_ok());
+        let config = config.unwrap();
+        // from the main file
+        let got = config.compute_pattern("square", None).unwrap();
+        assert_eq!(got.get_short_string(), "4-4-4-4");
+        // from patterns.d
+        let got = config.compute_pattern("sleepy", None).unwrap();
+        assert_eq!(got.get_short_string(), "4-7-8-0");
+        // collision with the main file
+        let got = config.compute_pattern("team/square", None).unwrap();
+        assert_eq!(got.get_short_string(), "5-5-5-5");
+        // extending the renamed pattern of the same file
+        let got = config.compute_pattern("square_long", None).unwrap();
+        assert_eq!(got.get_short_string(), "5-5-5-5");
+        assert_eq!(got.pattern_length, Some(PatternLength::Iterations(8)));
+        // from an included file, extending a pattern from patterns.d
+        let got = config.compute_pattern("sleepy_short", None).unwrap();
+        assert_eq!(got.get_short_string(), "4-7-8-0");
+        assert_eq!(got.pattern_length, Some(PatternLength::Iterations(2)));
+    }
+
+    #[test]
+    fn pattern_pack_only_patterns() {
+        let pack = toml::from_str::<PatternPack>("include = [\"more.toml\"]");
+        assert!(pack
+            .unwrap_err()
+            .to_string()
+            .contains("unknown field `include`"));
+        assert!(toml::from_str::<PatternPack>("").is_ok());
+    }
+
+    #[test]
+    fn pattern_filter() {
+        let config = get_standard_config();
+        let matching = |filter: &PatternFilter| {
+            let mut names: Vec<&str> = config
+                .patterns
+                .keys()
+                .filter(|name| filter.matches(name, &config.resolve_pattern(name).unwrap()))
+                .map(|name| name.as_str())
+                .collect();
+            names.sort();
+            names
+        };
+        let filter = PatternFilter {
+            tags: vec!["Sleep".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(matching(&filter), vec!["relax", "relax_short"]);
+        let filter = PatternFilter {
+            tags: vec!["sleep".to_string()],
+            difficulty: Some(Difficulty::Beginner),
+            ..Default::default()
+        };
+        assert_eq!(matching(&filter), vec!["relax_short"]);
+        let filter = PatternFilter {
+            text: Some("PRANA".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(matching(&filter), vec!["Pranayama", "long_and_deep"]);
+        assert_eq!(
+            matching(&PatternFilter::default()).len(),
+            config.patterns.len()
+        );
+    }
+
+    #[test]
+    fn pattern_list_formats() {
+        let config = get_standard_config();
+        let filter = PatternFilter {
+            tags: vec!["sleep".to_string()],
+            ..Default::default()
+        };
+        let entries = config.filter_patterns(&filter);
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&entries).unwrap()).unwrap();
+        assert_eq!(json[0]["name"], "relax");
+        assert_eq!(json[0]["breath_in"], 4);
+        assert_eq!(json[0]["iterations"], 8);
+        assert_eq!(json[1]["name"], "relax_short");
+        assert_eq!(json[1]["extends"], "relax");
+        let patterns = entries.iter().map(|e| (e.name, &e.pattern)).collect();
+        let output = toml::to_string(&PatternList { patterns }).unwrap();
+        assert!(output.contains("iterations = 8"), "{output}");
+        let got: Config = toml::from_str(&format!("time = 60\n{output}")).unwrap();
+        let got = got.compute_pattern("relax_short", None).unwrap();
+        assert_eq!(got.get_short_string(), "4-7-8-0");
+        assert_eq!(got.pattern_length, Some(PatternLength::Iterations(4)));
+    }
+
+    #[test]
+    fn end_policy_deserialization() {
+        let config = config_from_str(
+            r#"
+            time = 60
+            [patterns.base]
+            breath_in = 4
+            breath_out = 4
+            description = "base"
+            end_policy = "finish-cycle"
+            [patterns.child]
+            extends = "base"
+            [patterns.other]
+            extends = "base"
+            end_policy = "round-to-nearest-cycle"
+            "#,
+        )
+        .unwrap();
+        let got = config.compute_pattern("child", None).unwrap();
+        assert_eq!(got.end_policy, Some(EndPolicy::FinishCycle));
+        let got = config.compute_pattern("other", None).unwrap();
+        assert_eq!(got.end_policy, Some(EndPolicy::RoundToNearestCycle));
+    }
+
+    #[test]
+    fn phase_labels() {
+        let config = config_from_str(
+            r#"
+            time = 60
+            [labels]
+            breath_in = "Inhale"
+            hold_in = "Pause"
+            [patterns.base]
+            breath_in = 4
+            breath_out = 4
+            description = "base"
+            [patterns.base.labels]
+            breath_out = "Exhale slowly with pursed lips"
+            [patterns.child]
+            extends = "base"
+            labels = { breath_in = "Inhale through the nose" }
+            "#,
+        )
+        .unwrap();
+        let got = config.compute_pattern("base", None).unwrap();
+        assert_eq!(got.labels.breath_in.as_deref(), Some("Inhale"));
+        assert_eq!(got.labels.hold_in.as_deref(), Some("Pause"));
+        assert_eq!(
+            got.labels.breath_out.as_deref(),
+            Some("Exhale slowly with pursed lips")
+        );
+        assert_eq!(got.labels.hold_out, None);
+        let got = config.compute_pattern("child", None).unwrap();
+        assert_eq!(
+            got.labels.breath_in.as_deref(),
+            Some("Inhale through the nose")
+        );
+        assert_eq!(
+            got.labels.breath_out.as_deref(),
+            Some("Exhale slowly with pursed lips")
+        );
+    }
+
+    #[test]
+    fn localized_descriptions() {
+        use crate::i18n::Locale;
+        let config = config_from_str(
+            r#"
+            time = 60
+            [patterns.base]
+            breath_in = 4
+            breath_out = 4
+            description.en = "Relax"
+            description.it = "Rilassati"
+            [patterns.child]
+            extends = "base"
+            [patterns.only_it]
+            breath_in = 4
+            breath_out = 4
+            description = { it = "Solo italiano" }
+            "#,
+        )
+        .unwrap();
+        // the default locale is english
+        let got = config.compute_pattern("child", None).unwrap();
+        assert_eq!(got.description, "Relax");
+        let got = config.compute_pattern("only_it", None).unwrap();
+        assert_eq!(got.description, "Solo italiano");
+        let description = config.patterns["base"].description.clone().unwrap();
+        assert_eq!(description.resolve(Locale::It), "Rilassati");
     }
 }
@@ -0,0 +1,427 @@
+use crate::breathe::{self, BreathPhase, Status};
+use crate::hooks::Event;
+use crate::statusline;
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A client not reading its responses or events is dropped, instead of stalling the session
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+impl Status {
+    /// What the status bars show, None if the session is completed
+    fn statusline(&self) -> Option<statusline::Line<'_>> {
+        Some(statusline::Line {
+            phase: BreathPhase::from_name(self.phase.as_deref()?)?,
+            label: self.label.as_deref().unwrap_or_default(),
+            remaining: self.phase_duration? - self.phase_elapsed?,
+            cycle: self.cycle?,
+            cycles: self.cycles,
+            percentage: self.elapsed * 100 / self.session_length.max(1),
+            paused: self.paused,
+        })
+    }
+}
+
+/// A line sent by the clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Request {
+    Status,
+    Pause,
+    Resume,
+    Abort,
+    /// keep the connection open and receive an `EventMessage` for every event
+    Subscribe,
+}
+
+/// The line sent back for each request
+#[derive(Debug, Serialize)]
+struct Response<'a> {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<&'a Status>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// The line sent to the subscribers
+#[derive(Debug, Serialize)]
+struct EventMessage<'a> {
+    event: &'static str,
+    status: &'a Status,
+}
+
+struct Shared {
+    pattern: String,
+    status: Mutex<Option<Status>>,
+    /// pause requested by a client
+    paused: AtomicBool,
+    /// shared with the signal handlers
+    aborted: Arc<AtomicBool>,
+    subscribers: Mutex<Vec<UnixStream>>,
+}
+
+/// Create the directory of the socket, private to the user, if missing.
+/// An existing directory must not let the other users replace the socket
+fn check_dir(dir: &Path) -> anyhow::Result<()> {
+    if !dir.exists() {
+        return std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()));
+    }
+    let metadata =
+        std::fs::metadata(dir).with_context(|| format!("Failed to access {}", dir.display()))?;
+    let uid = crate::config::uid();
+    let writable_by_others = metadata.mode() & 0o022 != 0 && metadata.mode() & 0o1000 == 0;
+    if (metadata.uid() != uid && metadata.uid() != 0) || writable_by_others {
+        return Err(anyhow!(
+            "{} is not safe for the control socket: other users can write in it",
+            dir.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Accepts the clients of the running session, until dropped
+pub(crate) struct Server {
+    path: PathBuf,
+    /// device and inode of the socket, not to remove the one of a newer session
+    socket_id: (u64, u64),
+    closed: AtomicBool,
+    shared: Arc<Shared>,
+}
+
+impl Server {
+    /// Listen on `path`, replacing the socket left by a crashed session.
+    /// Fails if another session is running
+    pub(crate) fn start(
+        path: &Path,
+        pattern: &str,
+        aborted: Arc<AtomicBool>,
+    ) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            check_dir(dir)?;
+        }
+        if let Ok(metadata) = std::fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(anyhow!("{} exists and is not a socket", path.display()));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(anyhow!(
+                    "Another session is running, listening on {}",
+                    path.display()
+                ));
+            }
+            std::fs::remove_file(path)
+                .with_context(|| format!("Failed to remove the stale socket {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("Failed to listen on {}", path.display()))?;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("Failed to restrict the access to {}", path.display()))?;
+        let metadata = std::fs::symlink_metadata(path)
+            .with_context(|| format!("Failed to access {}", path.display()))?;
+        let shared = Arc::new(Shared {
+            pattern: pattern.to_string(),
+            status: Mutex::new(None),
+            paused: AtomicBool::new(false),
+            aborted,
+            subscribers: Mutex::new(Vec::new()),
+        });
+        {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let shared = shared.clone();
+                    std::thread::spawn(move || handle_client(stream, &shared));
+                }
+            });
+        }
+        Ok(Server {
+            path: path.to_path_buf(),
+            socket_id: (metadata.dev(), metadata.ino()),
+            closed: AtomicBool::new(false),
+            shared,
+        })
+    }
+
+    /// A client asked to pause the session
+    pub(crate) fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::Relaxed)
+    }
+
+    /// Refresh the status returned to the clients
+    pub(crate) fn update(&self, session: &breathe::BreathingSession, paused: bool) -> Status {
+        let status = Status::new(&self.shared.pattern, session, paused);
+        *self.shared.status.lock().unwrap() = Some(status.clone());
+        status
+    }
+
+    /// Refresh the status and send the event to the subscribers
+    pub(crate) fn publish(&self, event: Event, session: &breathe::BreathingSession, paused: bool) {
+        let status = self.update(session, paused);
+        let message = EventMessage {
+            event: event.name(),
+            status: &status,
+        };
+        let line = serde_json::to_string(&message).unwrap();
+        self.shared
+            .subscribers
+            .lock()
+            .unwrap()
+            .retain_mut(|stream| writeln!(stream, "{line}").is_ok());
+    }
+
+    /// Stop accepting new clients, the subscribers see the end of the stream.
+    /// Only the first call does something
+    pub(crate) fn close(&self) {
+        if self.closed.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        for stream in self.shared.subscribers.lock().unwrap().drain(..) {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+        let still_bound = std::fs::symlink_metadata(&self.path)
+            .is_ok_and(|metadata| (metadata.dev(), metadata.ino()) == self.socket_id);
+        if still_bound {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn handle_client(stream: UnixStream, shared: &Shared) {
+    let Ok(reader) = stream.try_clone() else {
+        return;
+    };
+    let mut writer = stream;
+    let _ = writer.set_write_timeout(Some(WRITE_TIMEOUT));
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = serde_json::from_str::<Request>(&line);
+        match request {
+            Ok(Request::Pause) => shared.paused.store(true, Ordering::Relaxed),
+            Ok(Request::Resume) => shared.paused.store(false, Ordering::Relaxed),
+            Ok(Request::Abort) => shared.aborted.store(true, Ordering::Relaxed),
+            Ok(Request::Status | Request::Subscribe) | Err(_) => {}
+        }
+        // while the subscribers are locked no event is sent, a new subscriber gets
+        // the response first, then all the events following the status in the response
+        let mut subscribers = shared.subscribers.lock().unwrap();
+        let status = shared.status.lock().unwrap().clone();
+        let response = match &request {
+            Ok(_) => Response {
+                ok: true,
+                status: status.as_ref(),
+                error: None,
+            },
+            Err(e) => Response {
+                ok: false,
+                status: None,
+                error: Some(format!("Invalid request: {e}")),
+            },
+        };
+        let line = serde_json::to_string(&response).unwrap();
+        if writeln!(writer, "{line}").is_err() {
+            return;
+        }
+        if let Ok(Request::Subscribe) = request {
+            if let Ok(subscriber) = writer.try_clone() {
+                subscribers.push(subscriber);
+            }
+        }
+    }
+}
+
+/// Client commands sent to the running session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum CtlCommand {
+    /// print the status of the session, in JSON
+    Status,
+    Pause,
+    Resume,
+    /// stop the session
+    Abort,
+    /// print the status every second, for the status bars, also when no session is running
+    Watch,
+}
+
+/// Send a command to the session listening on `path`, returning its status
+pub(crate) fn request(path: &Path, command: CtlCommand) -> anyhow::Result<Option<Status>> {
+    let mut stream = UnixStream::connect(path)
+        .with_context(|| format!("No session is running on {}", path.display()))?;
+    let request = match command {
+        CtlCommand::Status => Request::Status,
+        CtlCommand::Pause => Request::Pause,
+        CtlCommand::Resume => Request::Resume,
+        CtlCommand::Abort => Request::Abort,
+        CtlCommand::Watch => Request::Status,
+    };
+    writeln!(stream, "{}", serde_json::to_string(&request)?)?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    let response: serde_json::Value =
+        serde_json::from_str(&line).context("Invalid response from the session")?;
+    if response["ok"] != serde_json::Value::Bool(true) {
+        return Err(anyhow!(
+            "{}",
+            response["error"].as_str().unwrap_or("Request failed")
+        ));
+    }
+    Ok(serde_json::from_value(response["status"].clone()).ok())
+}
+
+/// Print the status of the session listening on `path` every second, forever
+pub(crate) fn watch(path: &Path, format: statusline::StatuslineFormat) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout();
+    loop {
+        // between two sessions the status bar is empty
+        let status = request(path, CtlCommand::Watch).ok().flatten();
+        let line = status.as_ref().and_then(Status::statusline);
+        writeln!(stdout, "{}", statusline::format(line.as_ref(), format))?;
+        stdout.flush()?;
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::breathe::test::session;
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("breathe-test-{}-{name}.sock", std::process::id()))
+    }
+
+    #[test]
+    fn control_requests() {
+        let path = socket_path("requests");
+        let aborted = Arc::new(AtomicBool::new(false));
+        let server = Server::start(&path, "relax", aborted.clone()).unwrap();
+        // only one session at a time
+        assert!(Server::start(&path, "other", Arc::default()).is_err());
+        let mut session = session();
+        for _ in 0..5 {
+            session.inc();
+        }
+        server.update(&session, false);
+        let status = request(&path, CtlCommand::Status).unwrap().unwrap();
+        assert_eq!(status.pattern, "relax");
+        assert_eq!(status.elapsed, 5);
+        assert_eq!(status.session_length, 20);
+        assert_eq!(status.cycle, Some(1));
+        assert_eq!(status.phase.as_deref(), Some("hold_in"));
+        assert_eq!(status.label.as_deref(), Some("Hold"));
+        assert_eq!(status.phase_elapsed, Some(1));
+        assert_eq!(status.phase_duration, Some(2));
+        let line = status.statusline().unwrap();
+        assert_eq!(line.phase, BreathPhase::HoldIn);
+        assert_eq!(line.remaining, 1);
+        assert_eq!(line.percentage, 25);
+
+        request(&path, CtlCommand::Pause).unwrap();
+        assert!(server.is_paused());
+        request(&path, CtlCommand::Resume).unwrap();
+        assert!(!server.is_paused());
+        request(&path, CtlCommand::Abort).unwrap();
+        assert!(aborted.load(Ordering::Relaxed));
+
+        drop(server);
+        assert!(!path.exists());
+        assert!(request(&path, CtlCommand::Status).is_err());
+    }
+
+    #[test]
+    fn control_subscribe() {
+        let path = socket_path("subscribe");
+        let server = Server::start(&path, "relax", Arc::default()).unwrap();
+        let mut session = session();
+        server.update(&session, false);
+        let mut stream = UnixStream::connect(&path).unwrap();
+        writeln!(stream, r#"{{"command":"subscribe"}}"#).unwrap();
+        let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+        let response: serde_json::Value =
+            serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(response["ok"], true);
+        assert_eq!(response["status"]["phase"], "breath_in");
+
+        for _ in 0..4 {
+            session.inc();
+        }
+        server.publish(Event::PhaseChange, &session, false);
+        let event: serde_json::Value =
+            serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(event["event"], "phase_change");
+        assert_eq!(event["status"]["phase"], "hold_in");
+
+        writeln!(stream, "nonsense").unwrap();
+        let response: serde_json::Value =
+            serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(response["ok"], false);
+        drop(server);
+        assert!(lines.next().is_none());
+    }
+
+    #[test]
+    fn control_stale_socket() {
+        let path = socket_path("stale");
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let server = Server::start(&path, "relax", Arc::default()).unwrap();
+        server.update(&session(), true);
+        assert!(request(&path, CtlCommand::Status).unwrap().unwrap().paused);
+        let mode = std::fs::metadata(&path).unwrap().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn control_close_once() {
+        let path = socket_path("close");
+        let server = Server::start(&path, "relax", Arc::default()).unwrap();
+        server.close();
+        assert!(!path.exists());
+        // the next session is not disturbed by the end of the previous one
+        let next = Server::start(&path, "relax", Arc::default()).unwrap();
+        drop(server);
+        assert!(request(&path, CtlCommand::Status).is_ok());
+        drop(next);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn control_not_a_socket() {
+        let path = socket_path("file");
+        std::fs::write(&path, "data").unwrap();
+        assert!(Server::start(&path, "relax", Arc::default()).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "data");
+        std::fs::remove_file(&path).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("breathe-test-{}-dir", std::process::id()));
+        let path = dir.join("breathe.sock");
+        drop(Server::start(&path, "relax", Arc::default()).unwrap());
+        let mode = std::fs::metadata(&dir).unwrap().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(Server::start(&path, "relax", Arc::default()).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+use crate::config;
+use crate::history;
+use crate::hooks;
+use crate::i18n::{self, Message};
+use anyhow::{anyhow, Context};
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How often the daemon checks if a reminder is due
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The `[reminders]` section of the configuration file
+#[derive(Debug, Default, Clone, Deserialize)]
+pub(crate) struct RemindersConfig {
+    /// time between two reminders, i.e. 90m
+    every: String,
+    /// beginning of the working hours, i.e. 09:00
+    from: Option<String>,
+    /// end of the working hours, i.e. 18:00
+    to: Option<String>,
+    /// i.e. ["mon", "tue", "wed", "thu", "fri"], every day by default
+    #[serde(default)]
+    weekdays: Vec<String>,
+    /// printed when a reminder is due
+    message: Option<String>,
+    /// shell command run when a reminder is due, i.e. to show a notification
+    command: Option<String>,
+    /// pattern of the session started when a reminder is due
+    pattern: Option<String>,
+}
+
+/// When the reminders are due
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Schedule {
+    /// seconds
+    every: u64,
+    from: Option<NaiveTime>,
+    to: Option<NaiveTime>,
+    /// empty means every day
+    weekdays: Vec<Weekday>,
+}
+
+impl Schedule {
+    fn from_config(config: &RemindersConfig) -> anyhow::Result<Self> {
+        let every = config::parse_time_length(&config::normalize(&config.every))
+            .with_context(|| format!("Invalid reminders interval {}", config.every))?;
+        let parse_time =
+            |time: &Option<String>| time.as_deref().map(config::parse_until).transpose();
+        let weekdays = config
+            .weekdays
+            .iter()
+            .map(|day| Weekday::from_str(day).map_err(|_| anyhow!("Invalid weekday {day}")))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Schedule {
+            every,
+            from: parse_time(&config.from)?,
+            to: parse_time(&config.to)?,
+            weekdays,
+        })
+    }
+
+    fn is_working_time(&self, now: NaiveDateTime) -> bool {
+        if !self.weekdays.is_empty() && !self.weekdays.contains(&now.weekday()) {
+            return false;
+        }
+        let time = now.time();
+        match (self.from, self.to) {
+            // working hours across midnight, i.e. 22:00-06:00
+            (Some(from), Some(to)) if to < from => time >= from || time < to,
+            (from, to) => from.is_none_or(|from| time >= from) && to.is_none_or(|to| time < to),
+        }
+    }
+
+    /// A reminder is due in the working hours, when nothing happened for a while.
+    /// `last` is the last reminder or the end of the last session, whatever comes later
+    fn is_due(&self, now: NaiveDateTime, last: Option<NaiveDateTime>) -> bool {
+        self.is_working_time(now)
+            && last.is_none_or(|last| (now - last).num_seconds() >= self.every as i64)
+    }
+}
+
+/// Where the daemon finds the other parts of breathe
+#[derive(Debug, Clone)]
+pub(crate) struct DaemonOpt {
+    pub(crate) config_file: PathBuf,
+    pub(crate) history: PathBuf,
+    /// don't record the sessions started by the reminders
+    pub(crate) no_history: bool,
+}
+
+/// Remind to practice, following the `[reminders]` section of the configuration, forever
+pub(crate) fn run(reminders: Option<&RemindersConfig>, opt: &DaemonOpt) -> anyhow::Result<()> {
+    let reminders = reminders
+        .ok_or_else(|| anyhow!("No [reminders] section in {}", opt.config_file.display()))?;
+    let schedule = Schedule::from_config(reminders)?;
+    // the first reminder is one interval after the daemon starts
+    let mut last_reminder = chrono::Local::now().naive_local();
+    loop {
+        let now = chrono::Local::now().naive_local();
+        let last_session = history::last_session_end(&opt.history)
+            .unwrap_or_else(|e| {
+                log::warn!("{e:#}");
+                None
+            })
+            .map(|end| end.naive_local());
+        let last = last_session.max(Some(last_reminder));
+        if schedule.is_due(now, last) {
+            remind(reminders, opt)?;
+            last_reminder = chrono::Local::now().naive_local();
+        }
+        std::thread::sleep(CHECK_INTERVAL);
+    }
+}
+
+fn remind(reminders: &RemindersConfig, opt: &DaemonOpt) -> anyhow::Result<()> {
+    let message = reminders
+        .message
+        .as_deref()
+        .unwrap_or(i18n::t(Message::Reminder));
+    println!("{}", message);
+    if let Some(command) = &reminders.command {
+        let env = vec![
+            ("BREATHE_REMINDER", message.to_string()),
+            (
+                "BREATHE_PATTERN",
+                reminders.pattern.clone().unwrap_or_default(),
+            ),
+        ];
+        match hooks::spawn(command, env) {
+            Ok(mut child) => {
+                std::thread::spawn(move || child.wait());
+            }
+            Err(e) => log::warn!("Failed to run the reminder command {command}: {e}"),
+        }
+    }
+    if let Some(pattern) = &reminders.pattern {
+        // a new process, as if started by the user, recorded in the same history if any
+        let mut command = std::process::Command::new(std::env::current_exe()?);
+        command
+            .arg("--config")
+            .arg(&opt.config_file)
+            .arg("--history")
+            .arg(&opt.history)
+            .args(["--pattern", pattern, "--yes"]);
+        if opt.no_history {
+            command.arg("--no-history");
+        }
+        let status = command.status().context("Failed to start the session")?;
+        if !status.success() {
+            log::warn!("The session {pattern} failed: {status}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(day: u32, time: &str) -> NaiveDateTime {
+        // 2024-05-06 is a monday
+        chrono::NaiveDate::from_ymd_opt(2024, 5, day)
+            .unwrap()
+            .and_time(config::parse_until(time).unwrap())
+    }
+
+    fn schedule(config: &str) -> anyhow::Result<Schedule> {
+        Schedule::from_config(&toml::from_str(config)?)
+    }
+
+    #[test]
+    fn schedule_from_config() {
+        let got = schedule(
+            r#"
+            every = "1h 30M"
+            from = "09:00"
+            to = "18:00"
+            weekdays = ["mon", "Friday"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            got,
+            Schedule {
+                every: 5400,
+                from: Some(config::parse_until("09:00").unwrap()),
+                to: Some(config::parse_until("18:00").unwrap()),
+                weekdays: vec![Weekday::Mon, Weekday::Fri],
+            }
+        );
+        assert!(schedule(r#"every = "0m""#).is_err());
+        assert!(schedule(r#"every = "often""#).is_err());
+        assert!(schedule("every = \"1h\"\nweekdays = [\"someday\"]").is_err());
+        assert!(schedule("every = \"1h\"\nfrom = \"9 o'clock\"").is_err());
+    }
+
+    #[test]
+    fn schedule_due() {
+        let got = schedule(
+            r#"
+            every = "90m"
+            from = "09:00"
+            to = "18:00"
+            weekdays = ["mon", "tue", "wed", "thu", "fri"]
+            "#,
+        )
+        .unwrap();
+        assert!(got.is_due(at(6, "10:30"), Some(at(6, "09:00"))));
+        assert!(!got.is_due(at(6, "10:29"), Some(at(6, "09:00"))));
+        assert!(got.is_due(at(6, "10:29"), None));
+        // outside the working hours
+        assert!(!got.is_due(at(6, "08:59"), None));
+        assert!(!got.is_due(at(6, "18:00"), None));
+        // saturday
+        assert!(!got.is_due(at(11, "10:30"), None));
+
+        let night = schedule("every = \"1h\"\nfrom = \"22:00\"\nto = \"06:00\"").unwrap();
+        assert!(night.is_due(at(6, "23:00"), None));
+        assert!(night.is_due(at(7, "05:59"), None));
+        assert!(!night.is_due(at(7, "12:00"), None));
+    }
+}
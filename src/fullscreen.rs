@@ -0,0 +1,239 @@
+use crate::breathe;
+use crate::i18n::{self, Message};
+use crate::theme;
+use crate::tui::Renderer;
+use std::io::Write;
+
+const ENTER_ALTERNATE_SCREEN: &str = "\x1b[?1049h";
+const LEAVE_ALTERNATE_SCREEN: &str = "\x1b[?1049l";
+const CURSOR_HOME: &str = "\x1b[H";
+const CLEAR_TO_END_OF_SCREEN: &str = "\x1b[J";
+const CLEAR_TO_END_OF_LINE: &str = "\x1b[K";
+/// Rows used below the shape for the phase and the session progress
+const STATUS_ROWS: usize = 4;
+const MIN_RADIUS: f64 = 1.0;
+
+/// What is shown in a frame
+#[derive(Debug, Clone, PartialEq)]
+struct FrameInfo<'a> {
+    label: &'a str,
+    /// seconds left in the phase
+    remaining: u64,
+    cycle: u64,
+    cycles: u64,
+    elapsed: u64,
+    session_length: u64,
+    /// how full the lungs are, from 0 to 1
+    fill: f64,
+}
+
+impl<'a> FrameInfo<'a> {
+    fn from_session(session: &'a breathe::BreathingSession, sub_second: f64) -> Option<Self> {
+        let segment = session.current_segment()?;
+        Some(FrameInfo {
+            label: session.label(segment.phase),
+            remaining: segment.end() - session.elapsed(),
+            cycle: segment.cycle + 1,
+            cycles: session.timeline().cycles(),
+            elapsed: session.elapsed(),
+            session_length: session.session_length,
+            fill: segment.phase.fill(session.eased_phase_progress(sub_second)),
+        })
+    }
+}
+
+fn center(text: &str, width: usize) -> String {
+    let len = console::measure_text_width(text);
+    let padding = width.saturating_sub(len) / 2;
+    format!("{}{}", " ".repeat(padding), text)
+}
+
+/// Rows left to the shape, none if the terminal is too small
+fn shape_rows(height: usize) -> usize {
+    height.saturating_sub(STATUS_ROWS)
+}
+
+/// Draw a circle growing with the breath, plus the status lines, in a `width` x `height` area.
+/// On tiny terminals, only the status lines fitting in `height` are drawn
+fn draw_frame(width: usize, height: usize, info: &FrameInfo) -> Vec<String> {
+    let shape_rows = shape_rows(height);
+    // terminal cells are about twice as tall as wide
+    let max_radius = (shape_rows as f64 / 2.0)
+        .min(width as f64 / 4.0)
+        .max(MIN_RADIUS + 1.0)
+        - 0.5;
+    let radius = MIN_RADIUS + info.fill * (max_radius - MIN_RADIUS);
+    let center_row = (shape_rows as f64 - 1.0) / 2.0;
+    let center_col = (width as f64 - 1.0) / 2.0;
+    let mut lines: Vec<String> = (0..shape_rows)
+        .map(|row| {
+            let line: String = (0..width)
+                .map(|col| {
+                    let dy = row as f64 - center_row;
+                    let dx = (col as f64 - center_col) / 2.0;
+                    if dx * dx + dy * dy <= radius * radius {
+                        '\u{2588}'
+                    } else {
+                        ' '
+                    }
+                })
+                .collect();
+            line.trim_end().to_string()
+        })
+        .collect();
+    if shape_rows > 0 {
+        lines.push(String::new());
+    }
+    lines.push(center(
+        &format!("{}  {}s", info.label, info.remaining),
+        width,
+    ));
+    lines.push(center(
+        &format!("{} {}/{}", i18n::t(Message::Cycle), info.cycle, info.cycles),
+        width,
+    ));
+    let bar_width = width.saturating_sub(8);
+    let done = (bar_width as u64 * info.elapsed)
+        .checked_div(info.session_length)
+        .map_or(bar_width, |done| done as usize);
+    let percent = (100 * info.elapsed)
+        .checked_div(info.session_length)
+        .unwrap_or(100);
+    lines.push(format!(
+        "{:>3}% [{}{}]",
+        percent,
+        "=".repeat(done),
+        " ".repeat(bar_width - done)
+    ));
+    lines.truncate(height);
+    lines
+}
+
+/// A full screen renderer, showing a circle growing and shrinking with the breath.
+/// It uses the alternate screen and adapts to the terminal size at every frame
+pub(crate) struct FullScreenRenderer {
+    term: console::Term,
+    theme: theme::Theme,
+}
+
+impl FullScreenRenderer {
+    pub(crate) fn new(theme: theme::Theme) -> Self {
+        FullScreenRenderer {
+            term: console::Term::stdout(),
+            theme,
+        }
+    }
+
+    fn draw(&mut self, session: &breathe::BreathingSession, sub_second: f64) {
+        let Some(info) = FrameInfo::from_session(session, sub_second) else {
+            return;
+        };
+        let (rows, cols) = self.term.size();
+        let lines = draw_frame(cols as usize, rows as usize, &info);
+        let shape_rows = shape_rows(rows as usize);
+        let style = session.current_segment().map(|segment| {
+            console::Style::from_dotted_str(self.theme.phase(segment.phase).foreground())
+        });
+        let lines: Vec<String> = lines
+            .into_iter()
+            .enumerate()
+            .map(|(i, line)| match &style {
+                Some(style) if i < shape_rows => {
+                    format!("{}{CLEAR_TO_END_OF_LINE}", style.apply_to(line))
+                }
+                _ => format!("{line}{CLEAR_TO_END_OF_LINE}"),
+            })
+            .collect();
+        // no newline after the last line, it would scroll the screen
+        let frame = format!(
+            "{CURSOR_HOME}{}{CLEAR_TO_END_OF_SCREEN}",
+            lines.join("\r\n")
+        );
+        let _ = self.term.write_all(frame.as_bytes());
+        let _ = self.term.flush();
+    }
+}
+
+impl Renderer for FullScreenRenderer {
+    fn start(&mut self, session: &breathe::BreathingSession) {
+        let _ = self.term.write_all(ENTER_ALTERNATE_SCREEN.as_bytes());
+        let _ = self.term.hide_cursor();
+        self.draw(session, 0.0);
+    }
+
+    fn tick(&mut self, session: &breathe::BreathingSession) {
+        self.draw(session, 0.0);
+    }
+
+    fn frame(&mut self, session: &breathe::BreathingSession, sub_second: f64) {
+        self.draw(session, sub_second);
+    }
+
+    fn finish(&mut self, _session: &breathe::BreathingSession) {
+        let _ = self.term.show_cursor();
+        let _ = self.term.write_all(LEAVE_ALTERNATE_SCREEN.as_bytes());
+        let _ = self.term.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn filled_cells(lines: &[String]) -> usize {
+        lines
+            .iter()
+            .map(|line| line.chars().filter(|&c| c == '\u{2588}').count())
+            .sum()
+    }
+
+    #[test]
+    fn frame_shape_grows_with_fill() {
+        let mut info = FrameInfo {
+            label: "Breathe in",
+            remaining: 3,
+            cycle: 2,
+            cycles: 8,
+            elapsed: 50,
+            session_length: 100,
+            fill: 0.0,
+        };
+        let empty = draw_frame(80, 24, &info);
+        info.fill = 0.5;
+        let half = draw_frame(80, 24, &info);
+        info.fill = 1.0;
+        let full = draw_frame(80, 24, &info);
+        assert!(filled_cells(&empty) > 0);
+        assert!(filled_cells(&empty) < filled_cells(&half));
+        assert!(filled_cells(&half) < filled_cells(&full));
+        assert_eq!(full.len(), 24);
+        assert!(full.iter().all(|line| line.chars().count() <= 80));
+        assert_eq!(full[21].trim(), "Breathe in  3s");
+        assert_eq!(full[22].trim(), "cycle 2/8");
+        assert!(full[23].starts_with(" 50% [====="));
+    }
+
+    #[test]
+    fn frame_tiny_terminal() {
+        let info = FrameInfo {
+            label: "Hold",
+            remaining: 1,
+            cycle: 1,
+            cycles: 1,
+            elapsed: 0,
+            session_length: 10,
+            fill: 1.0,
+        };
+        let got = draw_frame(4, 2, &info);
+        assert_eq!(got, vec!["Hold  1s", "cycle 1/1"]);
+        let got = draw_frame(20, 6, &info);
+        assert_eq!(got.len(), 6);
+        assert!(filled_cells(&got[..2]) > 0);
+    }
+
+    #[test]
+    fn frame_center_wide_label() {
+        assert_eq!(center("呼吸", 8), "  呼吸");
+        assert_eq!(center("ab", 8), "   ab");
+    }
+}
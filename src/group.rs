@@ -0,0 +1,285 @@
+use crate::breathe;
+use crate::config::{EndPolicy, Pattern, PatternLength};
+use crate::i18n;
+use crate::sound::SoundConfig;
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub(crate) const DEFAULT_PORT: u16 = 7878;
+/// Round trips measured to estimate the clock offset from the host
+const PINGS: usize = 8;
+/// A host not answering is given up, instead of waiting forever
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A line exchanged between the host and a member
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    /// sent by the members with their clock, in milliseconds since the epoch
+    Ping {
+        sent: i64,
+    },
+    /// the host adds its clock
+    Pong {
+        sent: i64,
+        time: i64,
+    },
+    Join,
+    /// the answer to `Join`, `start` on the clock of the host
+    Session {
+        name: String,
+        pattern: Box<Pattern>,
+        start: i64,
+    },
+}
+
+/// A session practiced together, started at the same time by every member
+#[derive(Debug, Clone)]
+pub(crate) struct GroupSession {
+    pub(crate) name: String,
+    pub(crate) pattern: Pattern,
+    /// on the local clock
+    pub(crate) start: SystemTime,
+}
+
+fn millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+fn from_millis(millis: i64) -> SystemTime {
+    if millis >= 0 {
+        UNIX_EPOCH + Duration::from_millis(millis as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_millis(millis.unsigned_abs())
+    }
+}
+
+/// Freeze the timeline, so that every member computes the same one,
+/// whatever its wall clock, i.e. with `until`, or its `--end-policy`
+fn freeze(mut pattern: Pattern) -> anyhow::Result<Pattern> {
+    let session =
+        breathe::BreathingSession::with_opt(&breathe::BreathSessionOpt { pattern: &pattern })?;
+    pattern.pattern_length = Some(PatternLength::Time(session.session_length));
+    pattern.end_policy = Some(EndPolicy::Truncate);
+    Ok(pattern)
+}
+
+/// Accept the members of the session in the background, until the process ends.
+/// The session starts `wait` from now
+pub(crate) fn host(
+    name: &str,
+    pattern: Pattern,
+    bind: IpAddr,
+    port: u16,
+    wait: Duration,
+) -> anyhow::Result<GroupSession> {
+    let address = SocketAddr::new(bind, port);
+    let listener =
+        TcpListener::bind(address).with_context(|| format!("Failed to listen on {address}"))?;
+    let session = GroupSession {
+        name: name.to_string(),
+        pattern: freeze(pattern)?,
+        start: SystemTime::now() + wait,
+    };
+    println!(
+        "{} {name} {} {}, {} `breathe join <{}>:{}`",
+        i18n::t(i18n::Message::Hosting),
+        i18n::t(i18n::Message::On),
+        listener.local_addr()?,
+        i18n::t(i18n::Message::JoinWith),
+        i18n::t(i18n::Message::ThisHost),
+        listener.local_addr()?.port()
+    );
+    accept_members(listener, session.clone());
+    Ok(session)
+}
+
+fn accept_members(listener: TcpListener, session: GroupSession) {
+    let session = Arc::new(session);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let session = session.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = handle_member(stream, &session) {
+                    log::warn!("{e:#}");
+                }
+            });
+        }
+    });
+}
+
+fn send(stream: &mut TcpStream, message: &Message) -> anyhow::Result<()> {
+    writeln!(stream, "{}", serde_json::to_string(message)?)?;
+    Ok(())
+}
+
+fn handle_member(stream: TcpStream, session: &GroupSession) -> anyhow::Result<()> {
+    let peer = stream.peer_addr()?;
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        match serde_json::from_str(&line?)? {
+            Message::Ping { sent } => {
+                let time = millis(SystemTime::now());
+                send(&mut writer, &Message::Pong { sent, time })?;
+            }
+            Message::Join => {
+                log::info!("{peer} joined the session");
+                let message = Message::Session {
+                    name: session.name.clone(),
+                    pattern: Box::new(session.pattern.clone()),
+                    start: millis(session.start),
+                };
+                return send(&mut writer, &message);
+            }
+            message => return Err(anyhow!("Unexpected message from {peer}: {message:?}")),
+        }
+    }
+    Ok(())
+}
+
+/// The clock of the host minus the local clock, in milliseconds, from the
+/// (sent, host time, received) of the round trips.
+/// The shortest round trip is the most accurate, assuming symmetric delays
+fn clock_offset(samples: &[(i64, i64, i64)]) -> Option<i64> {
+    samples
+        .iter()
+        .min_by_key(|(sent, _, received)| received - sent)
+        .map(|(sent, time, received)| time - (sent + received) / 2)
+}
+
+fn receive(lines: &mut impl Iterator<Item = std::io::Result<String>>) -> anyhow::Result<Message> {
+    let line = lines
+        .next()
+        .ok_or_else(|| anyhow!("The host closed the connection"))??;
+    Ok(serde_json::from_str(&line)?)
+}
+
+/// Join the session hosted at `address`, `host` or `host:port`.
+/// The sound follows the local configuration, not the one of the host
+pub(crate) fn join(address: &str) -> anyhow::Result<GroupSession> {
+    let address = match address.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, DEFAULT_PORT).to_string(),
+        Err(_) if address.contains(':') => address.to_string(),
+        Err(_) => format!("{address}:{DEFAULT_PORT}"),
+    };
+    let socket_address = address
+        .to_socket_addrs()
+        .with_context(|| format!("Invalid address {address}"))?
+        .next()
+        .ok_or_else(|| anyhow!("Invalid address {address}"))?;
+    let mut stream = TcpStream::connect_timeout(&socket_address, TIMEOUT)
+        .with_context(|| format!("Failed to connect to {address}"))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut lines = BufReader::new(stream.try_clone()?).lines();
+    let mut samples = Vec::with_capacity(PINGS);
+    for _ in 0..PINGS {
+        let sent = millis(SystemTime::now());
+        send(&mut stream, &Message::Ping { sent })?;
+        match receive(&mut lines)? {
+            Message::Pong { sent, time } => {
+                samples.push((sent, time, millis(SystemTime::now())));
+            }
+            message => return Err(anyhow!("Unexpected message from the host: {message:?}")),
+        }
+    }
+    let offset = clock_offset(&samples).unwrap_or_default();
+    log::info!("The clock of the host is {offset}ms ahead");
+    send(&mut stream, &Message::Join)?;
+    let Message::Session {
+        name,
+        mut pattern,
+        start,
+    } = receive(&mut lines)?
+    else {
+        return Err(anyhow!("Unexpected message from the host"));
+    };
+    pattern.sound = SoundConfig::default();
+    let session = GroupSession {
+        name,
+        start: from_millis(start - offset),
+        pattern: *pattern,
+    };
+    let length = breathe::BreathingSession::with_opt(&breathe::BreathSessionOpt {
+        pattern: &session.pattern,
+    })?
+    .session_length;
+    if session.start + Duration::from_secs(length) <= SystemTime::now() {
+        return Err(anyhow!("The session {} is already over", session.name));
+    }
+    Ok(session)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pattern() -> Pattern {
+        Pattern {
+            description: "box".to_string(),
+            pattern_length: Some(PatternLength::Time(25)),
+            end_policy: Some(EndPolicy::FinishCycle),
+            ..breathe::test::pattern()
+        }
+    }
+
+    #[test]
+    fn group_clock_offset() {
+        assert_eq!(clock_offset(&[]), None);
+        // the host is 1000ms ahead, the second round trip is the shortest
+        let samples = [(0, 1030, 40), (100, 1105, 110), (200, 1250, 300)];
+        assert_eq!(clock_offset(&samples), Some(1000));
+        assert_eq!(
+            from_millis(millis(UNIX_EPOCH - Duration::from_secs(1))),
+            UNIX_EPOCH - Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn group_freeze() {
+        let got = freeze(pattern()).unwrap();
+        assert_eq!(got.pattern_length, Some(PatternLength::Time(30)));
+        assert_eq!(got.end_policy, Some(EndPolicy::Truncate));
+        assert_eq!(
+            freeze(got.clone()).unwrap().pattern_length,
+            got.pattern_length
+        );
+    }
+
+    #[test]
+    fn group_join() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let hosted = GroupSession {
+            name: "box".to_string(),
+            pattern: freeze(pattern()).unwrap(),
+            start: SystemTime::now() + Duration::from_secs(10),
+        };
+        accept_members(listener, hosted.clone());
+        let got = join(&address).unwrap();
+        assert_eq!(got.name, "box");
+        assert_eq!((got.pattern.breath_in, got.pattern.hold_in), (4, Some(2)));
+        assert_eq!(got.pattern.pattern_length, Some(PatternLength::Time(30)));
+        // on the same machine, the offset is just the noise of the measure
+        let drift = match got.start.duration_since(hosted.start) {
+            Ok(drift) => drift,
+            Err(e) => e.duration(),
+        };
+        assert!(drift < Duration::from_millis(50), "{drift:?}");
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let over = GroupSession {
+            start: SystemTime::now() - Duration::from_secs(30),
+            ..hosted
+        };
+        accept_members(listener, over);
+        assert!(join(&address).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::Path;
+
+/// A practiced session, one JSON object per line in the history file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Entry {
+    pub(crate) pattern: String,
+    pub(crate) start: chrono::DateTime<chrono::Local>,
+    pub(crate) end: chrono::DateTime<chrono::Local>,
+    /// seconds actually practiced
+    pub(crate) elapsed: u64,
+    /// false if the session has been aborted
+    pub(crate) completed: bool,
+}
+
+/// Add an entry at the end of the history file, creating it if needed
+pub(crate) fn append(path: &Path, entry: &Entry) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// All the entries of the history file, an empty history if it doesn't exist
+pub(crate) fn read(path: &Path) -> anyhow::Result<Vec<Entry>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    Ok(content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(n, line)| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                log::warn!("Skipping line {} of {}: {e}", n + 1, path.display());
+                None
+            }
+        })
+        .collect())
+}
+
+/// When the last completed session ended, if any: an aborted session is not a practice
+pub(crate) fn last_session_end(
+    path: &Path,
+) -> anyhow::Result<Option<chrono::DateTime<chrono::Local>>> {
+    Ok(read(path)?
+        .into_iter()
+        .filter(|entry| entry.completed)
+        .map(|entry| entry.end)
+        .max())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn history_roundtrip() {
+        let path = std::env::temp_dir()
+            .join(format!("breathe-test-{}", std::process::id()))
+            .join("history.jsonl");
+        let _ = std::fs::remove_file(&path);
+        assert!(read(&path).unwrap().is_empty());
+        assert_eq!(last_session_end(&path).unwrap(), None);
+
+        let at = |h, m| chrono::Local.with_ymd_and_hms(2024, 5, 6, h, m, 0).unwrap();
+        let entry = Entry {
+            pattern: "relax".to_string(),
+            start: at(10, 0),
+            end: at(10, 5),
+            elapsed: 300,
+            completed: true,
+        };
+        append(&path, &entry).unwrap();
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"not json\n")
+            .unwrap();
+        // after the completed one
+        let aborted = Entry {
+            start: at(11, 0),
+            end: at(11, 1),
+            elapsed: 60,
+            completed: false,
+            ..entry.clone()
+        };
+        append(&path, &aborted).unwrap();
+        assert_eq!(read(&path).unwrap(), vec![entry, aborted]);
+        assert_eq!(last_session_end(&path).unwrap(), Some(at(10, 5)));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
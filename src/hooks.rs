@@ -0,0 +1,195 @@
+use crate::breathe;
+use serde::Deserialize;
+use std::process::{Child, Command, Stdio};
+
+/// The `[hooks]` section of the configuration file: shell commands run on the session events
+#[derive(Debug, Default, Clone, Deserialize)]
+pub(crate) struct HooksConfig {
+    on_session_start: Option<String>,
+    on_phase_change: Option<String>,
+    on_session_end: Option<String>,
+    /// the session has been interrupted, i.e. with ctrl-c
+    on_abort: Option<String>,
+}
+
+/// Something happened to a running session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Event {
+    SessionStart,
+    PhaseChange,
+    Pause,
+    Resume,
+    SessionEnd,
+    Abort,
+}
+
+impl Event {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Event::SessionStart => "session_start",
+            Event::PhaseChange => "phase_change",
+            Event::Pause => "pause",
+            Event::Resume => "resume",
+            Event::SessionEnd => "session_end",
+            Event::Abort => "abort",
+        }
+    }
+}
+
+/// Runs the hook commands of a session
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Hooks {
+    config: HooksConfig,
+    pattern: String,
+}
+
+impl Hooks {
+    pub(crate) fn new(config: HooksConfig, pattern: &str) -> Self {
+        Hooks {
+            config,
+            pattern: pattern.to_string(),
+        }
+    }
+
+    fn command(&self, event: Event) -> Option<&str> {
+        match event {
+            Event::SessionStart => self.config.on_session_start.as_deref(),
+            Event::PhaseChange => self.config.on_phase_change.as_deref(),
+            Event::SessionEnd => self.config.on_session_end.as_deref(),
+            Event::Abort => self.config.on_abort.as_deref(),
+            Event::Pause | Event::Resume => None,
+        }
+    }
+
+    /// The variables describing the session to the hook commands
+    fn environment(
+        &self,
+        event: Event,
+        session: &breathe::BreathingSession,
+    ) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("BREATHE_EVENT", event.name().to_string()),
+            ("BREATHE_PATTERN", self.pattern.clone()),
+            ("BREATHE_SESSION_LENGTH", session.session_length.to_string()),
+            ("BREATHE_ELAPSED", session.elapsed().to_string()),
+            ("BREATHE_CYCLES", session.timeline().cycles().to_string()),
+        ];
+        if let Some(segment) = session.current_segment() {
+            env.extend([
+                ("BREATHE_CYCLE", (segment.cycle + 1).to_string()),
+                ("BREATHE_PHASE", segment.phase.name().to_string()),
+                (
+                    "BREATHE_PHASE_LABEL",
+                    session.label(segment.phase).to_string(),
+                ),
+                ("BREATHE_PHASE_DURATION", segment.duration.to_string()),
+            ]);
+        }
+        env
+    }
+
+    /// Run the command of the event, if any, without waiting for it
+    pub(crate) fn run(&self, event: Event, session: &breathe::BreathingSession) {
+        let Some(command) = self.command(event) else {
+            return;
+        };
+        let command = command.to_string();
+        match spawn(&command, self.environment(event, session)) {
+            // wait in the background, so that a slow hook never stalls the session
+            Ok(mut child) => {
+                std::thread::spawn(move || match child.wait() {
+                    Ok(status) if !status.success() => {
+                        log::warn!("Hook {command} failed: {status}");
+                    }
+                    Err(e) => log::warn!("Hook {command} failed: {e}"),
+                    _ => {}
+                });
+            }
+            Err(e) => log::warn!("Failed to run the hook {command}: {e}"),
+        }
+    }
+}
+
+pub(crate) fn spawn(command: &str, env: Vec<(&'static str, String)>) -> std::io::Result<Child> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell
+        .arg(command)
+        .envs(env)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::breathe::test::session;
+
+    fn get<'a>(env: &'a [(&str, String)], name: &str) -> Option<&'a str> {
+        env.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn hooks_environment() {
+        let hooks = Hooks::new(HooksConfig::default(), "relax");
+        let mut session = session();
+        for _ in 0..5 {
+            session.inc();
+        }
+        let env = hooks.environment(Event::PhaseChange, &session);
+        assert_eq!(get(&env, "BREATHE_EVENT"), Some("phase_change"));
+        assert_eq!(get(&env, "BREATHE_PATTERN"), Some("relax"));
+        assert_eq!(get(&env, "BREATHE_SESSION_LENGTH"), Some("20"));
+        assert_eq!(get(&env, "BREATHE_ELAPSED"), Some("5"));
+        assert_eq!(get(&env, "BREATHE_CYCLE"), Some("1"));
+        assert_eq!(get(&env, "BREATHE_CYCLES"), Some("2"));
+        assert_eq!(get(&env, "BREATHE_PHASE"), Some("hold_in"));
+        assert_eq!(get(&env, "BREATHE_PHASE_LABEL"), Some("Hold"));
+        assert_eq!(get(&env, "BREATHE_PHASE_DURATION"), Some("2"));
+        while !session.is_completed() {
+            session.inc();
+        }
+        let env = hooks.environment(Event::SessionEnd, &session);
+        assert_eq!(get(&env, "BREATHE_ELAPSED"), Some("20"));
+        assert_eq!(get(&env, "BREATHE_PHASE"), None);
+    }
+
+    #[test]
+    fn hooks_config() {
+        let config: HooksConfig = toml::from_str(
+            r#"
+            on_session_start = "notify-send start"
+            on_abort = "notify-send abort"
+            "#,
+        )
+        .unwrap();
+        let hooks = Hooks::new(config, "relax");
+        assert_eq!(
+            hooks.command(Event::SessionStart),
+            Some("notify-send start")
+        );
+        assert_eq!(hooks.command(Event::PhaseChange), None);
+        assert_eq!(hooks.command(Event::Abort), Some("notify-send abort"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hooks_spawn() {
+        let mut child = spawn(
+            r#"test "$BREATHE_EVENT" = session_end"#,
+            vec![("BREATHE_EVENT", "session_end".to_string())],
+        )
+        .unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+}
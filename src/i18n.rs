@@ -0,0 +1,163 @@
+use std::sync::OnceLock;
+
+/// The supported languages of the user interface
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum Locale {
+    #[default]
+    En,
+    It,
+}
+
+impl Locale {
+    /// The language code, as used in the configuration file, i.e. `description.it`
+    pub(crate) fn code(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::It => "it",
+        }
+    }
+
+    /// Parse a POSIX locale, like `it_IT.UTF-8`
+    fn from_posix(tag: &str) -> Option<Self> {
+        let language = tag.split(['_', '.', '@', '-']).next()?.to_ascii_lowercase();
+        match language.as_str() {
+            "en" => Some(Locale::En),
+            "it" => Some(Locale::It),
+            _ => None,
+        }
+    }
+
+    /// The locale from the environment: `LC_ALL`, `LC_MESSAGES` or `LANG`
+    pub(crate) fn from_env() -> Self {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|value| Locale::from_posix(&value))
+            .unwrap_or_default()
+    }
+}
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Select the language of the user interface; only the first call has effect
+pub(crate) fn set_locale(locale: Locale) {
+    let _ = LOCALE.set(locale);
+}
+
+pub(crate) fn locale() -> Locale {
+    LOCALE.get().copied().unwrap_or_default()
+}
+
+/// The user facing messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Message {
+    Description,
+    BreatheIn,
+    Hold,
+    BreatheOut,
+    SessionLength,
+    EndPolicy,
+    Effective,
+    Seconds,
+    Cycle,
+    Cycles,
+    StartPrompt,
+    StartingIn,
+    SessionCompleted,
+    Reminder,
+    Pattern,
+    Extends,
+    Difficulty,
+    Tags,
+    Hosting,
+    On,
+    JoinWith,
+    ThisHost,
+    Start,
+    Stop,
+}
+
+/// The message in the current language
+pub(crate) fn t(message: Message) -> &'static str {
+    translate(locale(), message)
+}
+
+fn translate(locale: Locale, message: Message) -> &'static str {
+    match locale {
+        Locale::En => match message {
+            Message::Description => "Description",
+            Message::BreatheIn => "Breathe in",
+            Message::Hold => "Hold",
+            Message::BreatheOut => "Breathe out",
+            Message::SessionLength => "Session length",
+            Message::EndPolicy => "End policy",
+            Message::Effective => "Effective",
+            Message::Seconds => "seconds",
+            Message::Cycle => "cycle",
+            Message::Cycles => "cycles",
+            Message::StartPrompt => "Would you like to start the breathing session?",
+            Message::StartingIn => "Starting in",
+            Message::SessionCompleted => "Session completed",
+            Message::Reminder => "Time for a breathing session",
+            Message::Pattern => "Pattern",
+            Message::Extends => "Extends",
+            Message::Difficulty => "Difficulty",
+            Message::Tags => "Tags",
+            Message::Hosting => "Hosting",
+            Message::On => "on",
+            Message::JoinWith => "join with",
+            Message::ThisHost => "this host",
+            Message::Start => "Start",
+            Message::Stop => "Stop",
+        },
+        Locale::It => match message {
+            Message::Description => "Descrizione",
+            Message::BreatheIn => "Inspira",
+            Message::Hold => "Trattieni",
+            Message::BreatheOut => "Espira",
+            Message::SessionLength => "Durata",
+            Message::EndPolicy => "Conclusione",
+            Message::Effective => "Effettiva",
+            Message::Seconds => "secondi",
+            Message::Cycle => "ciclo",
+            Message::Cycles => "cicli",
+            Message::StartPrompt => "Vuoi iniziare la sessione di respirazione?",
+            Message::StartingIn => "Si comincia tra",
+            Message::SessionCompleted => "Sessione completata",
+            Message::Reminder => "È ora di una sessione di respirazione",
+            Message::Pattern => "Schema",
+            Message::Extends => "Estende",
+            Message::Difficulty => "Difficoltà",
+            Message::Tags => "Etichette",
+            Message::Hosting => "Sessione",
+            Message::On => "su",
+            Message::JoinWith => "per partecipare",
+            Message::ThisHost => "questo host",
+            Message::Start => "Inizia",
+            Message::Stop => "Ferma",
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locale_from_posix() {
+        assert_eq!(Locale::from_posix("it_IT.UTF-8"), Some(Locale::It));
+        assert_eq!(Locale::from_posix("en_US"), Some(Locale::En));
+        assert_eq!(Locale::from_posix("IT"), Some(Locale::It));
+        assert_eq!(Locale::from_posix("de_DE.UTF-8"), None);
+        assert_eq!(Locale::from_posix("C"), None);
+    }
+
+    #[test]
+    fn translations() {
+        assert_eq!(translate(Locale::En, Message::BreatheIn), "Breathe in");
+        assert_eq!(translate(Locale::It, Message::BreatheIn), "Inspira");
+        // the default locale is english
+        assert_eq!(t(Message::Hold), "Hold");
+    }
+}
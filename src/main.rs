@@ -1,8 +1,23 @@
+mod audio;
 mod breathe;
 mod config;
+#[cfg(unix)]
+mod control;
+mod daemon;
+mod fullscreen;
+mod group;
+mod history;
+mod hooks;
+mod i18n;
+mod serve;
+mod sound;
+mod statusline;
+mod text;
+mod theme;
 mod tui;
+mod visual;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
@@ -13,6 +28,7 @@ struct Opt {
         long = "config",
         short = 'c',
         default_value_os_t = config::get_default_config_file(),
+        global = true
     )]
     config_file: PathBuf,
     #[arg(
@@ -23,15 +39,173 @@ struct Opt {
         global = true
     )]
     verbosity_level: u8,
+    /// the control socket of the running session
+    #[arg(long, default_value_os_t = config::get_default_socket(), global = true)]
+    socket: PathBuf,
+    /// the log of the practiced sessions
+    #[arg(long, default_value_os_t = config::get_default_history_file(), global = true)]
+    history: PathBuf,
+    /// don't record the session in the history
+    #[arg(long, global = true)]
+    no_history: bool,
+    /// don't listen on the control socket
+    #[arg(long)]
+    no_control: bool,
     /// select the breathe pattern you want to practice
     #[arg(short, long, default_value = "relax")]
     pattern: String,
     /// list all available breathe patterns
     #[arg(short, long)]
     list: bool,
-    /// specify a different duartion in the form of durationType=nn
-    #[arg(short = 'd', long)]
+    /// output format of the pattern list
+    #[arg(long, value_enum, default_value_t, requires = "list")]
+    format: config::OutputFormat,
+    /// specify a different duration, like 20m, 1h30m, 10x, 10 rounds or until=07:30
+    #[arg(short = 'd', long, global = true)]
     pattern_length: Option<config::PatternLength>,
+    /// practice an ad-hoc pattern, in the form breath_in-hold_in-breath_out-hold_out (i.e. 4-7-8-0)
+    #[arg(long, conflicts_with_all = ["pattern", "list"])]
+    custom: Option<config::Pattern>,
+    /// the description of the ad-hoc pattern
+    #[arg(long, requires = "custom")]
+    description: Option<String>,
+    /// save the ad-hoc pattern in the configuration file with the given name
+    #[arg(long, requires = "custom")]
+    save: Option<String>,
+    /// how to end a time based session: truncate, finish-cycle or round-to-nearest-cycle
+    #[arg(long, value_enum, global = true)]
+    end_policy: Option<config::EndPolicy>,
+    /// start the session without asking for confirmation
+    #[arg(short, long)]
+    yes: bool,
+    /// wait some seconds before starting the session
+    #[arg(long, default_value_t = 0)]
+    countdown: u64,
+    /// how to show the session
+    #[arg(long, value_enum, default_value_t)]
+    ui: tui::UiKind,
+    /// with --ui text, print the seconds left in the phase every second
+    #[arg(long)]
+    ticks: bool,
+    /// the output of --ui statusline and ctl watch
+    #[arg(long, value_enum, default_value_t, global = true)]
+    statusline_format: statusline::StatuslineFormat,
+    /// frames per second of the animations, 1 disables the smooth progress
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..=120))]
+    fps: u32,
+    /// ring the terminal bell on every phase change
+    #[arg(long)]
+    bell: bool,
+    /// color theme: default, ocean, forest or mono
+    #[arg(long)]
+    theme: Option<String>,
+    /// when to use colors
+    #[arg(long, value_enum, default_value_t, global = true)]
+    color: theme::ColorMode,
+    /// language of the messages, by default taken from LANG
+    #[arg(long, value_enum, global = true)]
+    lang: Option<i18n::Locale>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// list the available breathe patterns
+    List {
+        /// show only the patterns with this tag (can be repeated)
+        #[arg(short, long)]
+        tag: Vec<String>,
+        /// show only the patterns with this difficulty
+        #[arg(long)]
+        difficulty: Option<config::Difficulty>,
+        /// how much information to show for each pattern
+        #[arg(long, value_enum, default_value_t)]
+        view: config::ListView,
+        /// output format
+        #[arg(long, value_enum, default_value_t)]
+        format: config::OutputFormat,
+    },
+    /// search the patterns by name and description
+    Search {
+        /// the text to look for, case insensitive
+        text: String,
+        /// how much information to show for each pattern
+        #[arg(long, value_enum, default_value_t)]
+        view: config::ListView,
+        /// output format
+        #[arg(long, value_enum, default_value_t)]
+        format: config::OutputFormat,
+    },
+    /// control the running session
+    #[cfg(unix)]
+    Ctl {
+        #[arg(value_enum)]
+        command: control::CtlCommand,
+    },
+    /// print the computed session of a pattern, in JSON
+    Plan {
+        /// the pattern name
+        pattern: String,
+    },
+    /// synthesize a guided audio track of a pattern session, in WAV
+    RenderAudio {
+        /// the pattern name
+        pattern: String,
+        /// the WAV file to write
+        #[arg(short, long)]
+        output: PathBuf,
+        /// frequency of the tone in Hz, when the lungs are empty
+        #[arg(long, default_value_t = 220.0)]
+        pitch: f64,
+        /// from 0 to 1
+        #[arg(long, default_value_t = 0.5)]
+        volume: f64,
+        /// samples per second
+        #[arg(long, default_value_t = 22050, value_parser = clap::value_parser!(u32).range(8000..=192000))]
+        sample_rate: u32,
+    },
+    /// draw a looping animation of a pattern cycle, to share it
+    RenderVisual {
+        /// the pattern name
+        pattern: String,
+        /// the file to write
+        #[arg(short, long)]
+        output: PathBuf,
+        #[arg(long, value_enum, default_value_t)]
+        format: visual::VisualFormat,
+        /// width and height, in pixels
+        #[arg(long, default_value_t = 240, value_parser = clap::value_parser!(u16).range(64..=1024))]
+        size: u16,
+        /// frames per second of the GIF animation
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..=50))]
+        fps: u32,
+    },
+    /// remind to practice, following the [reminders] section of the configuration
+    Daemon,
+    /// serve a web page to practice in the browser
+    Serve {
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        /// the address to listen on, i.e. 0.0.0.0 to reach the page from other devices
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: std::net::IpAddr,
+    },
+    /// host a group session, started at the same time by all the members
+    Host {
+        /// the pattern name
+        pattern: String,
+        #[arg(long, default_value_t = group::DEFAULT_PORT)]
+        port: u16,
+        /// the address to listen on
+        #[arg(long, default_value = "0.0.0.0")]
+        bind: std::net::IpAddr,
+        /// seconds left to the members to join, before the start
+        #[arg(long, default_value_t = 10)]
+        wait: u64,
+    },
+    /// join a group session, in the form host or host:port
+    Join { address: String },
 }
 
 fn get_level_filter(verbosity_level: u8) -> log::LevelFilter {
@@ -43,16 +217,185 @@ fn get_level_filter(verbosity_level: u8) -> log::LevelFilter {
         _ => log::LevelFilter::Trace,
     }
 }
+/// Write machine-readable output, a reader closing the pipe early (i.e. `head`) is not an error
+pub(crate) fn write_output(text: &str) -> anyhow::Result<()> {
+    use std::io::Write;
+    let mut stdout = std::io::stdout().lock();
+    match stdout
+        .write_all(text.as_bytes())
+        .and_then(|()| stdout.flush())
+    {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     let opt = Opt::parse();
-    env_logger::builder().filter_level(get_level_filter(opt.verbosity_level));
-    let config = config::from_file(&opt.config_file)?;
-    if opt.list {
-        config.print_pattern_list();
+    env_logger::builder()
+        .filter_level(get_level_filter(opt.verbosity_level))
+        .init();
+    theme::set_color_mode(opt.color);
+    i18n::set_locale(opt.lang.unwrap_or_else(i18n::Locale::from_env));
+    // the running session is controlled without reading the configuration
+    #[cfg(unix)]
+    if let Some(Command::Ctl { command }) = opt.command {
+        if command == control::CtlCommand::Watch {
+            return control::watch(&opt.socket, opt.statusline_format);
+        }
+        if let Some(status) = control::request(&opt.socket, command)? {
+            if command == control::CtlCommand::Status {
+                println!("{}", serde_json::to_string(&status)?);
+            }
+        }
         return Ok(());
     }
-    let pattern = config.compute_pattern(&opt.pattern, opt.pattern_length)?;
+    let config = match opt.command {
+        // the pattern of a group session comes from the host, the configuration is optional
+        Some(Command::Join { .. }) if !opt.config_file.exists() => config::Config::empty(),
+        _ => config::from_file(&opt.config_file)?,
+    };
+    let group = match opt.command {
+        Some(Command::List {
+            tag,
+            difficulty,
+            view,
+            format,
+        }) => {
+            let filter = config::PatternFilter {
+                tags: tag,
+                difficulty,
+                ..Default::default()
+            };
+            return config.print_pattern_list(&filter, view, format);
+        }
+        Some(Command::Search { text, view, format }) => {
+            let filter = config::PatternFilter {
+                text: Some(text),
+                ..Default::default()
+            };
+            return config.print_pattern_list(&filter, view, format);
+        }
+        #[cfg(unix)]
+        Some(Command::Ctl { .. }) => unreachable!("handled before reading the configuration"),
+        Some(Command::Plan { pattern }) => {
+            let mut computed = config.compute_pattern(&pattern, opt.pattern_length)?;
+            computed.end_policy = opt.end_policy.or(computed.end_policy);
+            let bso = breathe::BreathSessionOpt { pattern: &computed };
+            let plan = breathe::SessionPlan::new(&pattern, &bso)?;
+            return write_output(&format!("{}\n", serde_json::to_string_pretty(&plan)?));
+        }
+        Some(Command::RenderAudio {
+            pattern,
+            output,
+            pitch,
+            volume,
+            sample_rate,
+        }) => {
+            let mut computed = config.compute_pattern(&pattern, opt.pattern_length)?;
+            computed.end_policy = opt.end_policy.or(computed.end_policy);
+            let bso = breathe::BreathSessionOpt { pattern: &computed };
+            let audio_opt = audio::AudioOpt {
+                pitch,
+                volume,
+                sample_rate,
+            };
+            return audio::render(&bso, &audio_opt, &output);
+        }
+        Some(Command::RenderVisual {
+            pattern,
+            output,
+            format,
+            size,
+            fps,
+        }) => {
+            let computed = config.compute_pattern(&pattern, None)?;
+            let visual_opt = visual::VisualOpt { format, size, fps };
+            return visual::render(&computed, &visual_opt, &output);
+        }
+        Some(Command::Daemon) => {
+            let daemon_opt = daemon::DaemonOpt {
+                config_file: opt.config_file,
+                history: opt.history,
+                no_history: opt.no_history,
+            };
+            return daemon::run(config.reminders.as_ref(), &daemon_opt);
+        }
+        Some(Command::Serve { port, bind }) => {
+            let serve_opt = serve::ServeOpt {
+                bind,
+                port,
+                history: (!opt.no_history).then_some(opt.history),
+                pattern_length: opt.pattern_length,
+                end_policy: opt.end_policy,
+            };
+            return serve::run(config, serve_opt);
+        }
+        Some(Command::Host {
+            pattern,
+            port,
+            bind,
+            wait,
+        }) => {
+            let mut computed = config.compute_pattern(&pattern, opt.pattern_length)?;
+            computed.end_policy = opt.end_policy.or(computed.end_policy);
+            let wait = std::time::Duration::from_secs(wait);
+            Some(group::host(&pattern, computed, bind, port, wait)?)
+        }
+        Some(Command::Join { address }) => Some(group::join(&address)?),
+        None => None,
+    };
+    if opt.list {
+        return config.print_pattern_list(
+            &config::PatternFilter::default(),
+            config::ListView::default(),
+            opt.format,
+        );
+    }
+    let (name, mut pattern, start_at) = if let Some(group) = group {
+        // the timeline is the one of the host, whatever the local options
+        let pattern = config.complete_pattern(group.pattern, None);
+        (group.name, pattern, Some(group.start))
+    } else if let Some(mut custom) = opt.custom {
+        if let Some(description) = opt.description {
+            custom.description = description;
+        }
+        custom.end_policy = opt.end_policy;
+        custom.pattern_length = opt.pattern_length;
+        let pattern = config.complete_pattern(custom.clone(), opt.pattern_length);
+        // never save a pattern that can't be practiced
+        breathe::BreathingSession::with_opt(&breathe::BreathSessionOpt { pattern: &pattern })?;
+        if let Some(name) = &opt.save {
+            config::save_pattern(&opt.config_file, name, &custom)?;
+        }
+        let name = opt.save.unwrap_or_else(|| "custom".to_string());
+        (name, pattern, None)
+    } else {
+        let mut pattern = config.compute_pattern(&opt.pattern, opt.pattern_length)?;
+        pattern.end_policy = opt.end_policy.or(pattern.end_policy);
+        (opt.pattern, pattern, None)
+    };
+    if opt.bell {
+        pattern.sound.bell = Some(true);
+    }
     let bso = breathe::BreathSessionOpt { pattern: &pattern };
-    tui::run(bso);
+    let ui_opt = tui::UiOpt {
+        yes: opt.yes,
+        countdown: opt.countdown,
+        ui: opt.ui,
+        ticks: opt.ticks,
+        fps: opt.fps,
+        statusline_format: opt.statusline_format,
+        theme: theme::Theme::from_config(&config.theme, opt.theme.as_deref())?,
+        hooks: hooks::Hooks::new(config.hooks.clone(), &name),
+        pattern_name: name,
+        socket: (!opt.no_control).then_some(opt.socket),
+        history: (!opt.no_history).then_some(opt.history),
+        start_at,
+    };
+    if !tui::run(bso, ui_opt)? {
+        // the conventional exit status of a process interrupted by SIGINT
+        std::process::exit(130);
+    }
     Ok(())
 }
@@ -0,0 +1,334 @@
+use crate::breathe::{self, Status};
+use crate::config::{Config, EndPolicy, PatternFilter, PatternLength};
+use crate::history;
+use crate::i18n::{self, Message};
+use anyhow::Context;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The page animating the sessions streamed by `/session`, see `index`
+const INDEX: &str = include_str!("serve.html");
+const TEXT: &str = "text/plain; charset=utf-8";
+
+/// How the web server runs the sessions
+#[derive(Debug, Clone)]
+pub(crate) struct ServeOpt {
+    pub(crate) bind: IpAddr,
+    pub(crate) port: u16,
+    /// where the sessions are recorded, if any
+    pub(crate) history: Option<PathBuf>,
+    pub(crate) pattern_length: Option<PatternLength>,
+    pub(crate) end_policy: Option<EndPolicy>,
+}
+
+struct Shared {
+    config: Config,
+    opt: ServeOpt,
+    /// one second, shorter in the tests
+    tick: Duration,
+}
+
+/// Serve the web page and the sessions, forever
+pub(crate) fn run(config: Config, opt: ServeOpt) -> anyhow::Result<()> {
+    let address = SocketAddr::new(opt.bind, opt.port);
+    let listener =
+        TcpListener::bind(address).with_context(|| format!("Failed to listen on {address}"))?;
+    println!("Serving on http://{}", listener.local_addr()?);
+    serve(
+        listener,
+        Arc::new(Shared {
+            config,
+            opt,
+            tick: Duration::from_secs(1),
+        }),
+    );
+    Ok(())
+}
+
+/// The page, with the labels in the language of the user interface
+fn index() -> String {
+    INDEX
+        .replace("{{start}}", i18n::t(Message::Start))
+        .replace("{{stop}}", i18n::t(Message::Stop))
+}
+
+fn serve(listener: TcpListener, shared: Arc<Shared>) {
+    for stream in listener.incoming().flatten() {
+        let shared = shared.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = handle_client(stream, &shared) {
+                log::warn!("{e:#}");
+            }
+        });
+    }
+}
+
+/// The request line of an HTTP request
+#[derive(Debug, PartialEq, Eq)]
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Parse a line like `GET /session?pattern=relax HTTP/1.1`
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.split_whitespace();
+        let method = parts.next()?.to_string();
+        let target = parts.next()?;
+        parts
+            .next()
+            .filter(|version| version.starts_with("HTTP/"))?;
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode(key), decode(value))
+            })
+            .collect();
+        Some(Request {
+            method,
+            path: decode(path),
+            query,
+        })
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Decode the `+` and `%XX` escapes of an URL component
+fn decode(src: &str) -> String {
+    let mut bytes = Vec::with_capacity(src.len());
+    let mut i = 0;
+    while i < src.len() {
+        let byte = src.as_bytes()[i];
+        let escaped = src
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(escaped)) => {
+                bytes.push(escaped);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => bytes.push(b' '),
+            _ => bytes.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+fn handle_client(mut stream: TcpStream, shared: &Shared) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    // the headers are not needed
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+    let Some(request) = Request::parse(&line) else {
+        return respond(&mut stream, "400 Bad Request", TEXT, b"Bad request");
+    };
+    log::info!("{} {}", request.method, request.path);
+    if request.method != "GET" {
+        return respond(&mut stream, "405 Method Not Allowed", TEXT, b"Only GET");
+    }
+    match request.path.as_str() {
+        "/" => respond(
+            &mut stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            index().as_bytes(),
+        ),
+        "/patterns" => {
+            let patterns = shared.config.pattern_list_json(&PatternFilter::default())?;
+            respond(
+                &mut stream,
+                "200 OK",
+                "application/json",
+                patterns.as_bytes(),
+            )
+        }
+        "/session" => session(&mut stream, &request, shared),
+        _ => respond(&mut stream, "404 Not Found", TEXT, b"Not found"),
+    }
+}
+
+/// Write a Server-Sent Event
+fn send(stream: &mut TcpStream, event: &str, status: &Status) -> std::io::Result<()> {
+    let data = serde_json::to_string(status)?;
+    write!(stream, "event: {event}\ndata: {data}\n\n")?;
+    stream.flush()
+}
+
+/// Run a session, streaming a `status` event every second and an `end` event at the end.
+/// The session is aborted when the page is closed
+fn session(stream: &mut TcpStream, request: &Request, shared: &Shared) -> anyhow::Result<()> {
+    let name = request.param("pattern").unwrap_or("relax");
+    let mut pattern = match shared
+        .config
+        .compute_pattern(name, shared.opt.pattern_length)
+    {
+        Ok(pattern) => pattern,
+        Err(e) => return respond(stream, "404 Not Found", TEXT, e.to_string().as_bytes()),
+    };
+    pattern.end_policy = shared.opt.end_policy.or(pattern.end_policy);
+    let bso = breathe::BreathSessionOpt { pattern: &pattern };
+    let mut session = match breathe::BreathingSession::with_opt(&bso) {
+        Ok(session) => session,
+        Err(e) => return respond(stream, "400 Bad Request", TEXT, e.to_string().as_bytes()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n"
+    )?;
+    let start = chrono::Local::now();
+    let started = Instant::now();
+    let mut ticks = 0;
+    let completed = loop {
+        let status = Status::new(name, &session, false);
+        if session.is_completed() {
+            let _ = send(stream, "end", &status);
+            break true;
+        }
+        if send(stream, "status", &status).is_err() {
+            log::info!("Session {name} aborted by the client");
+            break false;
+        }
+        // following the wall clock, as the terminal does
+        ticks += 1;
+        std::thread::sleep(
+            (started + shared.tick * ticks).saturating_duration_since(Instant::now()),
+        );
+        session.inc();
+    };
+    if let Some(path) = &shared.opt.history {
+        let entry = history::Entry {
+            pattern: name.to_string(),
+            start,
+            end: chrono::Local::now(),
+            elapsed: session.elapsed(),
+            completed,
+        };
+        history::append(path, &entry)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn serve_request_parse() {
+        let got = Request::parse("GET /session?pattern=pack%2Fbox+4&x HTTP/1.1\r\n").unwrap();
+        assert_eq!(
+            got,
+            Request {
+                method: "GET".to_string(),
+                path: "/session".to_string(),
+                query: vec![
+                    ("pattern".to_string(), "pack/box 4".to_string()),
+                    ("x".to_string(), String::new()),
+                ],
+            }
+        );
+        assert_eq!(got.param("pattern"), Some("pack/box 4"));
+        assert_eq!(got.param("y"), None);
+        assert_eq!(Request::parse("GET / HTTP/1.0").unwrap().query, Vec::new());
+        assert_eq!(Request::parse("GET /"), None);
+        assert_eq!(Request::parse(""), None);
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%e2%82%ac%zz"), "€%zz");
+    }
+
+    fn get(address: SocketAddr, target: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serve_session() {
+        let dir = std::env::temp_dir().join(format!("breathe-serve-{}", std::process::id()));
+        let history = dir.join("history.jsonl");
+        let config: Config = toml::from_str(
+            r#"
+            iterations = 2
+            [patterns.quick]
+            breath_in = 1
+            breath_out = 1
+            description = "quick"
+            "#,
+        )
+        .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let shared = Arc::new(Shared {
+            config,
+            opt: ServeOpt {
+                bind: address.ip(),
+                port: address.port(),
+                history: Some(history.clone()),
+                pattern_length: None,
+                end_policy: None,
+            },
+            tick: Duration::from_millis(10),
+        });
+        std::thread::spawn(move || serve(listener, shared));
+
+        let index = get(address, "/");
+        assert!(index.starts_with("HTTP/1.1 200 OK"));
+        assert!(index.contains("EventSource"));
+        assert!(index.contains(r#"<button id="start">Start</button>"#));
+        assert!(get(address, "/patterns").contains(r#""name":"quick""#));
+        assert!(get(address, "/nothing").starts_with("HTTP/1.1 404"));
+        assert!(get(address, "/session?pattern=slow").starts_with("HTTP/1.1 404"));
+
+        let events = get(address, "/session?pattern=quick");
+        assert!(events.contains("Content-Type: text/event-stream"));
+        assert_eq!(events.matches("event: status").count(), 4);
+        assert!(events.contains(r#""phase":"breath_out","label":"Breathe out""#));
+        assert!(events.contains(
+            r#"event: end
+data: {"pattern":"quick","paused":false,"elapsed":4,"session_length":4,"cycles":2}"#
+        ));
+        let entries = history::read(&history).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].pattern, "quick");
+        assert_eq!(entries[0].elapsed, 4);
+        assert!(entries[0].completed);
+    }
+}
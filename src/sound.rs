@@ -0,0 +1,249 @@
+use crate::breathe::BreathPhase;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// Pause between two bells of the same phase, otherwise terminals merge them
+const BELL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The `[sound]` section of the configuration file, or of a pattern.
+/// Audible cues played on every phase change
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct SoundConfig {
+    /// ring the terminal bell
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) bell: Option<bool>,
+    /// command playing the sound files, `{file}` is replaced by the file path.
+    /// Without `{file}`, the path is the last argument
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) player: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) breath_in: Option<PhaseSound>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) hold_in: Option<PhaseSound>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) breath_out: Option<PhaseSound>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) hold_out: Option<PhaseSound>,
+}
+
+/// The cue of a single phase
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct PhaseSound {
+    /// how many times the bell rings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) bells: Option<u32>,
+    /// the sound file given to the player
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) file: Option<PathBuf>,
+}
+
+impl PhaseSound {
+    fn or(mut self, fallback: &PhaseSound) -> Self {
+        self.bells = self.bells.or(fallback.bells);
+        if self.file.is_none() {
+            self.file.clone_from(&fallback.file);
+        }
+        self
+    }
+}
+
+impl SoundConfig {
+    /// Fill the fields not specified here with the ones in `fallback`
+    pub(crate) fn or(mut self, fallback: &SoundConfig) -> Self {
+        self.bell = self.bell.or(fallback.bell);
+        if self.player.is_none() {
+            self.player.clone_from(&fallback.player);
+        }
+        for (phase, fallback) in [
+            (&mut self.breath_in, &fallback.breath_in),
+            (&mut self.hold_in, &fallback.hold_in),
+            (&mut self.breath_out, &fallback.breath_out),
+            (&mut self.hold_out, &fallback.hold_out),
+        ] {
+            *phase = match (phase.take(), fallback) {
+                (Some(sound), Some(fallback)) => Some(sound.or(fallback)),
+                (sound, fallback) => sound.or_else(|| fallback.clone()),
+            };
+        }
+        self
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        *self == SoundConfig::default()
+    }
+
+    fn phase(&self, phase: BreathPhase) -> Option<&PhaseSound> {
+        match phase {
+            BreathPhase::BreathIn => self.breath_in.as_ref(),
+            BreathPhase::HoldIn => self.hold_in.as_ref(),
+            BreathPhase::BreathOut => self.breath_out.as_ref(),
+            BreathPhase::HoldOut => self.hold_out.as_ref(),
+        }
+    }
+}
+
+/// The bells of each phase when not configured, so that phases can be told apart
+fn default_bells(phase: BreathPhase) -> u32 {
+    match phase {
+        BreathPhase::BreathIn => 1,
+        BreathPhase::HoldIn | BreathPhase::HoldOut => 2,
+        BreathPhase::BreathOut => 3,
+    }
+}
+
+/// Plays the audible cues of a session
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Cues {
+    config: SoundConfig,
+}
+
+impl Cues {
+    pub(crate) fn new(config: SoundConfig) -> Self {
+        Cues { config }
+    }
+
+    /// How many times the bell rings at the beginning of the phase
+    fn bells(&self, phase: BreathPhase) -> u32 {
+        if !self.config.bell.unwrap_or(false) {
+            return 0;
+        }
+        self.config
+            .phase(phase)
+            .and_then(|sound| sound.bells)
+            .unwrap_or_else(|| default_bells(phase))
+    }
+
+    /// The player command line for the phase, if any.
+    /// An empty player is none, the sound file must not become the program
+    fn player_command(&self, phase: BreathPhase) -> Option<Vec<String>> {
+        let player = self.config.player.as_ref()?;
+        let file = self.config.phase(phase)?.file.as_ref()?;
+        let file = file.to_string_lossy();
+        let mut args: Vec<String> = player.split_whitespace().map(str::to_string).collect();
+        if args.is_empty() {
+            return None;
+        }
+        if args.iter().any(|arg| arg.contains("{file}")) {
+            for arg in &mut args {
+                *arg = arg.replace("{file}", &file);
+            }
+        } else {
+            args.push(file.into_owned());
+        }
+        Some(args)
+    }
+
+    /// Called at the beginning of every phase, doesn't wait for the cue to end
+    pub(crate) fn phase_started(&self, phase: BreathPhase) {
+        let bells = self.bells(phase);
+        if bells > 0 {
+            thread::spawn(move || ring(bells));
+        }
+        if let Some(args) = self.player_command(phase) {
+            match std::process::Command::new(&args[0])
+                .args(&args[1..])
+                .stdin(std::process::Stdio::null())
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .spawn()
+            {
+                // wait in the background, so that no zombie is left
+                Ok(mut child) => {
+                    thread::spawn(move || child.wait());
+                }
+                Err(e) => log::warn!("Failed to run the player {}: {e}", args[0]),
+            }
+        }
+    }
+}
+
+/// Ring the terminal bell, on stderr to keep the standard output clean
+fn ring(bells: u32) {
+    let mut stderr = std::io::stderr();
+    for i in 0..bells {
+        if i > 0 {
+            thread::sleep(BELL_INTERVAL);
+        }
+        let _ = stderr.write_all(b"\x07");
+        let _ = stderr.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cues_bells() {
+        let cues = Cues::default();
+        assert_eq!(cues.bells(BreathPhase::BreathIn), 0);
+        let cues = Cues::new(SoundConfig {
+            bell: Some(true),
+            hold_out: Some(PhaseSound {
+                bells: Some(4),
+                file: None,
+            }),
+            ..Default::default()
+        });
+        assert_eq!(cues.bells(BreathPhase::BreathIn), 1);
+        assert_eq!(cues.bells(BreathPhase::HoldIn), 2);
+        assert_eq!(cues.bells(BreathPhase::BreathOut), 3);
+        assert_eq!(cues.bells(BreathPhase::HoldOut), 4);
+    }
+
+    #[test]
+    fn cues_player_command() {
+        let in_sound = PhaseSound {
+            bells: None,
+            file: Some(PathBuf::from("/tmp/in.wav")),
+        };
+        let mut config = SoundConfig {
+            player: Some("paplay".to_string()),
+            breath_in: Some(in_sound),
+            ..Default::default()
+        };
+        let cues = Cues::new(config.clone());
+        assert_eq!(
+            cues.player_command(BreathPhase::BreathIn),
+            Some(vec!["paplay".to_string(), "/tmp/in.wav".to_string()])
+        );
+        assert_eq!(cues.player_command(BreathPhase::BreathOut), None);
+        config.player = Some("mpv --volume=50 {file} --really-quiet".to_string());
+        let cues = Cues::new(config.clone());
+        assert_eq!(
+            cues.player_command(BreathPhase::BreathIn).unwrap(),
+            ["mpv", "--volume=50", "/tmp/in.wav", "--really-quiet"]
+        );
+        config.player = Some(" ".to_string());
+        let cues = Cues::new(config);
+        assert_eq!(cues.player_command(BreathPhase::BreathIn), None);
+    }
+
+    #[test]
+    fn sound_config_or() {
+        let global: SoundConfig = toml::from_str(
+            r#"
+            bell = true
+            player = "aplay"
+            breath_in = { bells = 2, file = "in.wav" }
+            "#,
+        )
+        .unwrap();
+        let pattern: SoundConfig = toml::from_str(
+            r#"
+            bell = false
+            breath_in = { file = "other.wav" }
+            "#,
+        )
+        .unwrap();
+        let got = pattern.or(&global);
+        assert_eq!(got.bell, Some(false));
+        assert_eq!(got.player.as_deref(), Some("aplay"));
+        let breath_in = got.breath_in.unwrap();
+        assert_eq!(breath_in.bells, Some(2));
+        assert_eq!(breath_in.file, Some(PathBuf::from("other.wav")));
+    }
+}
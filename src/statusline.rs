@@ -0,0 +1,209 @@
+use crate::breathe::{self, BreathPhase};
+use crate::tui::Renderer;
+use serde::Serialize;
+
+/// The output formats of the status bars
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum StatuslineFormat {
+    /// plain text, i.e. for tmux or i3blocks in persist mode
+    #[default]
+    Plain,
+    /// JSON for a waybar custom module with `return-type: json`
+    Waybar,
+    /// JSON for i3blocks with `format=json`
+    I3blocks,
+}
+
+fn glyph(phase: BreathPhase) -> &'static str {
+    match phase {
+        BreathPhase::BreathIn => "\u{25b2}",
+        BreathPhase::HoldIn => "\u{25a0}",
+        BreathPhase::BreathOut => "\u{25bc}",
+        BreathPhase::HoldOut => "\u{25a1}",
+    }
+}
+
+const PAUSED_GLYPH: &str = "\u{2016}";
+
+/// What is shown in the status bar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Line<'a> {
+    pub(crate) phase: BreathPhase,
+    pub(crate) label: &'a str,
+    /// seconds left in the phase
+    pub(crate) remaining: u64,
+    pub(crate) cycle: u64,
+    pub(crate) cycles: u64,
+    /// progress of the session, from 0 to 100
+    pub(crate) percentage: u64,
+    pub(crate) paused: bool,
+}
+
+impl<'a> Line<'a> {
+    /// The line of a running session, None if it's completed
+    pub(crate) fn from_session(session: &'a breathe::BreathingSession) -> Option<Self> {
+        let segment = session.current_segment()?;
+        Some(Line {
+            phase: segment.phase,
+            label: session.label(segment.phase),
+            remaining: segment.end() - session.elapsed(),
+            cycle: segment.cycle + 1,
+            cycles: session.timeline().cycles(),
+            percentage: session.elapsed() * 100 / session.session_length.max(1),
+            paused: false,
+        })
+    }
+
+    fn short_text(&self) -> String {
+        let glyph = if self.paused {
+            PAUSED_GLYPH
+        } else {
+            glyph(self.phase)
+        };
+        format!("{glyph} {}s", self.remaining)
+    }
+
+    fn text(&self) -> String {
+        format!("{} {}/{}", self.short_text(), self.cycle, self.cycles)
+    }
+}
+
+#[derive(Serialize)]
+struct WaybarLine<'a> {
+    text: String,
+    tooltip: &'a str,
+    class: &'a str,
+    percentage: u64,
+}
+
+#[derive(Serialize)]
+struct I3blocksLine {
+    full_text: String,
+    short_text: String,
+}
+
+/// The line printed for the status bar, `None` means no session is running
+pub(crate) fn format(line: Option<&Line>, format: StatuslineFormat) -> String {
+    match format {
+        StatuslineFormat::Plain => line.map(Line::text).unwrap_or_default(),
+        StatuslineFormat::Waybar => {
+            let waybar = match line {
+                Some(line) => WaybarLine {
+                    text: line.text(),
+                    tooltip: line.label,
+                    class: if line.paused {
+                        "paused"
+                    } else {
+                        line.phase.name()
+                    },
+                    percentage: line.percentage,
+                },
+                None => WaybarLine {
+                    text: String::new(),
+                    tooltip: "",
+                    class: "idle",
+                    percentage: 0,
+                },
+            };
+            serde_json::to_string(&waybar).unwrap()
+        }
+        StatuslineFormat::I3blocks => {
+            let i3blocks = I3blocksLine {
+                full_text: line.map(Line::text).unwrap_or_default(),
+                short_text: line.map(Line::short_text).unwrap_or_default(),
+            };
+            serde_json::to_string(&i3blocks).unwrap()
+        }
+    }
+}
+
+/// A renderer for the status bars, printing a line every second
+#[derive(Debug, Default)]
+pub(crate) struct StatuslineRenderer {
+    pub(crate) format: StatuslineFormat,
+}
+
+impl StatuslineRenderer {
+    fn print(&self, session: &breathe::BreathingSession) {
+        let line = Line::from_session(session);
+        println!("{}", format(line.as_ref(), self.format));
+    }
+}
+
+impl Renderer for StatuslineRenderer {
+    fn start(&mut self, session: &breathe::BreathingSession) {
+        self.print(session);
+    }
+
+    fn tick(&mut self, session: &breathe::BreathingSession) {
+        // the last line is printed by `finish`
+        if !session.is_completed() {
+            self.print(session);
+        }
+    }
+
+    fn finish(&mut self, _session: &breathe::BreathingSession) {
+        // clear the status bar
+        println!("{}", format(None, self.format));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::breathe::test::session;
+
+    #[test]
+    fn statusline_from_session() {
+        let mut session = session();
+        for _ in 0..5 {
+            session.inc();
+        }
+        let line = Line::from_session(&session).unwrap();
+        assert_eq!(
+            line,
+            Line {
+                phase: BreathPhase::HoldIn,
+                label: "Hold",
+                remaining: 1,
+                cycle: 1,
+                cycles: 2,
+                percentage: 25,
+                paused: false,
+            }
+        );
+        while !session.is_completed() {
+            session.inc();
+        }
+        assert_eq!(Line::from_session(&session), None);
+    }
+
+    #[test]
+    fn statusline_formats() {
+        let mut line = Line {
+            phase: BreathPhase::BreathIn,
+            label: "Breathe in",
+            remaining: 3,
+            cycle: 2,
+            cycles: 5,
+            percentage: 30,
+            paused: false,
+        };
+        assert_eq!(format(Some(&line), StatuslineFormat::Plain), "▲ 3s 2/5");
+        assert_eq!(
+            format(Some(&line), StatuslineFormat::Waybar),
+            r#"{"text":"▲ 3s 2/5","tooltip":"Breathe in","class":"breath_in","percentage":30}"#
+        );
+        assert_eq!(
+            format(Some(&line), StatuslineFormat::I3blocks),
+            r#"{"full_text":"▲ 3s 2/5","short_text":"▲ 3s"}"#
+        );
+        line.paused = true;
+        assert_eq!(format(Some(&line), StatuslineFormat::Plain), "‖ 3s 2/5");
+        assert_eq!(format(None, StatuslineFormat::Plain), "");
+        assert_eq!(
+            format(None, StatuslineFormat::Waybar),
+            r#"{"text":"","tooltip":"","class":"idle","percentage":0}"#
+        );
+    }
+}
@@ -0,0 +1,111 @@
+use crate::breathe;
+use crate::i18n::{self, Message};
+use crate::tui::Renderer;
+
+/// A plain renderer, printing one line per phase.
+/// Suitable for screen readers and for logging, it's used when the output is not a terminal
+#[derive(Debug, Default)]
+pub(crate) struct TextRenderer {
+    /// print a line with the seconds left in the phase, every second
+    pub(crate) ticks: bool,
+}
+
+impl TextRenderer {
+    fn phase_line(&self, session: &breathe::BreathingSession) -> Option<String> {
+        let segment = session.current_segment()?;
+        Some(format!(
+            "{} \u{2014} {}s ({} {}/{})",
+            session.label(segment.phase),
+            segment.duration,
+            i18n::t(Message::Cycle),
+            segment.cycle + 1,
+            session.timeline().cycles()
+        ))
+    }
+
+    /// The line to print after a second of the session, if any
+    fn tick_line(&self, session: &breathe::BreathingSession) -> Option<String> {
+        if session.is_state_changed() {
+            return self.phase_line(session);
+        }
+        if !self.ticks {
+            return None;
+        }
+        let segment = session.current_segment()?;
+        Some(format!("  {}", segment.end() - session.elapsed()))
+    }
+}
+
+impl Renderer for TextRenderer {
+    fn start(&mut self, session: &breathe::BreathingSession) {
+        if let Some(line) = self.phase_line(session) {
+            println!("{line}");
+        }
+    }
+
+    fn tick(&mut self, session: &breathe::BreathingSession) {
+        if let Some(line) = self.tick_line(session) {
+            println!("{line}");
+        }
+    }
+
+    fn finish(&mut self, session: &breathe::BreathingSession) {
+        // an aborted session ends before its time
+        if session.is_completed() {
+            println!("{}", i18n::t(Message::SessionCompleted));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::{Pattern, PatternLength};
+
+    fn collect_lines(renderer: &TextRenderer, pattern: &Pattern) -> Vec<String> {
+        let bso = breathe::BreathSessionOpt { pattern };
+        let mut session = breathe::BreathingSession::with_opt(&bso).unwrap();
+        let mut result: Vec<String> = renderer.phase_line(&session).into_iter().collect();
+        while !session.is_completed() {
+            session.inc();
+            result.extend(renderer.tick_line(&session));
+        }
+        result
+    }
+
+    #[test]
+    fn text_renderer_lines() {
+        let pattern = Pattern {
+            breath_in: 2,
+            hold_in: Some(1),
+            breath_out: 3,
+            hold_out: None,
+            pattern_length: Some(PatternLength::Iterations(2)),
+            description: "Test pattern".to_string(),
+            ..Default::default()
+        };
+        let got = collect_lines(&TextRenderer::default(), &pattern);
+        assert_eq!(
+            got,
+            vec![
+                "Breathe in \u{2014} 2s (cycle 1/2)",
+                "Hold \u{2014} 1s (cycle 1/2)",
+                "Breathe out \u{2014} 3s (cycle 1/2)",
+                "Breathe in \u{2014} 2s (cycle 2/2)",
+                "Hold \u{2014} 1s (cycle 2/2)",
+                "Breathe out \u{2014} 3s (cycle 2/2)",
+            ]
+        );
+        let got = collect_lines(&TextRenderer { ticks: true }, &pattern);
+        assert_eq!(
+            got[..5],
+            [
+                "Breathe in \u{2014} 2s (cycle 1/2)",
+                "  1",
+                "Hold \u{2014} 1s (cycle 1/2)",
+                "Breathe out \u{2014} 3s (cycle 1/2)",
+                "  2",
+            ]
+        );
+    }
+}
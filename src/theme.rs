@@ -0,0 +1,275 @@
+use crate::breathe::BreathPhase;
+use anyhow::anyhow;
+use serde::Deserialize;
+
+const DEFAULT_THEME: &str = "default";
+
+/// The `[theme]` section of the configuration file.
+/// `name` selects a built-in theme, the other fields override it
+#[derive(Debug, Default, Clone, Deserialize)]
+pub(crate) struct ThemeConfig {
+    name: Option<String>,
+    breath_in: Option<StyleConfig>,
+    hold_in: Option<StyleConfig>,
+    breath_out: Option<StyleConfig>,
+    hold_out: Option<StyleConfig>,
+    /// the style of the session progress bar
+    session: Option<StyleConfig>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+struct StyleConfig {
+    color: Option<String>,
+    progress_chars: Option<String>,
+    spinner_chars: Option<String>,
+}
+
+/// How a progress bar looks like
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Style {
+    /// in the indicatif form `filled/empty`, i.e. `cyan/blue`
+    pub(crate) color: String,
+    /// filled, current and empty characters, i.e. `=>-`
+    pub(crate) progress_chars: String,
+    /// the last one is shown when the bar is completed
+    pub(crate) spinner_chars: String,
+}
+
+impl Style {
+    fn new(color: &str, progress_chars: &str, spinner_chars: &str) -> Self {
+        Style {
+            color: color.to_string(),
+            progress_chars: progress_chars.to_string(),
+            spinner_chars: spinner_chars.to_string(),
+        }
+    }
+
+    fn apply(&mut self, config: &StyleConfig) -> anyhow::Result<()> {
+        if let Some(color) = &config.color {
+            if color.is_empty()
+                || !color
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '/' || c == '_' || c == '.')
+            {
+                return Err(anyhow!("Invalid theme color {color}"));
+            }
+            self.color.clone_from(color);
+        }
+        if let Some(progress_chars) = &config.progress_chars {
+            if progress_chars.chars().count() < 2 {
+                return Err(anyhow!(
+                    "Invalid theme progress_chars {progress_chars}: at least 2 characters required"
+                ));
+            }
+            // the progress bars require characters of the same, visible, width
+            let mut widths = progress_chars
+                .chars()
+                .map(|c| console::measure_text_width(c.encode_utf8(&mut [0; 4])));
+            let width = widths.next().unwrap_or_default();
+            if width == 0 || widths.any(|w| w != width) {
+                return Err(anyhow!(
+                    "Invalid theme progress_chars {progress_chars}: all the characters must have the same width"
+                ));
+            }
+            self.progress_chars.clone_from(progress_chars);
+        }
+        if let Some(spinner_chars) = &config.spinner_chars {
+            if spinner_chars.chars().count() < 2 {
+                return Err(anyhow!(
+                    "Invalid theme spinner_chars {spinner_chars}: at least 2 characters required"
+                ));
+            }
+            self.spinner_chars.clone_from(spinner_chars);
+        }
+        Ok(())
+    }
+
+    /// The color of the filled part, usable with `console::Style::from_dotted_str`
+    pub(crate) fn foreground(&self) -> &str {
+        self.color.split('/').next().unwrap_or_default()
+    }
+}
+
+/// The styles of the progress display, one for each breath phase plus the session one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Theme {
+    breath_in: Style,
+    hold_in: Style,
+    breath_out: Style,
+    hold_out: Style,
+    session: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::builtin(DEFAULT_THEME).unwrap()
+    }
+}
+
+pub(crate) const BUILTIN_THEMES: [&str; 4] = ["default", "ocean", "forest", "mono"];
+
+impl Theme {
+    fn uniform(style: Style) -> Self {
+        Theme {
+            breath_in: style.clone(),
+            hold_in: style.clone(),
+            breath_out: style.clone(),
+            hold_out: style.clone(),
+            session: style,
+        }
+    }
+
+    pub(crate) fn builtin(name: &str) -> Option<Self> {
+        let spinner = r#"-\|/ "#;
+        match name {
+            "default" => Some(Theme::uniform(Style::new("cyan/blue", "=>-", spinner))),
+            "ocean" => Some(Theme {
+                breath_in: Style::new(
+                    "cyan/blue",
+                    "\u{2588}\u{2593}\u{2591}",
+                    "\u{25dc}\u{25dd}\u{25de}\u{25df} ",
+                ),
+                hold_in: Style::new(
+                    "blue/black",
+                    "\u{2588}\u{2593}\u{2591}",
+                    "\u{00b7}\u{2022} ",
+                ),
+                breath_out: Style::new(
+                    "blue/cyan",
+                    "\u{2588}\u{2593}\u{2591}",
+                    "\u{25dc}\u{25dd}\u{25de}\u{25df} ",
+                ),
+                hold_out: Style::new(
+                    "white/black",
+                    "\u{2588}\u{2593}\u{2591}",
+                    "\u{00b7}\u{2022} ",
+                ),
+                session: Style::new("cyan/blue", "\u{2588}\u{2593}\u{2591}", spinner),
+            }),
+            "forest" => Some(Theme {
+                breath_in: Style::new("green/black", "#>.", spinner),
+                hold_in: Style::new("yellow/black", "#>.", spinner),
+                breath_out: Style::new("green/black", "#<.", spinner),
+                hold_out: Style::new("white/black", "#>.", spinner),
+                session: Style::new("green/yellow", "=>-", spinner),
+            }),
+            "mono" => Some(Theme::uniform(Style::new("white/white", "#>-", spinner))),
+            _ => None,
+        }
+    }
+
+    /// Build the theme from the configuration, `name` overrides the built-in theme selected there
+    pub(crate) fn from_config(config: &ThemeConfig, name: Option<&str>) -> anyhow::Result<Self> {
+        let name = name.or(config.name.as_deref()).unwrap_or(DEFAULT_THEME);
+        let mut theme = Theme::builtin(name).ok_or_else(|| {
+            anyhow!(
+                "Theme {name} not found, available themes: {}",
+                BUILTIN_THEMES.join(", ")
+            )
+        })?;
+        for (style, style_config) in [
+            (&mut theme.breath_in, &config.breath_in),
+            (&mut theme.hold_in, &config.hold_in),
+            (&mut theme.breath_out, &config.breath_out),
+            (&mut theme.hold_out, &config.hold_out),
+            (&mut theme.session, &config.session),
+        ] {
+            if let Some(style_config) = style_config {
+                style.apply(style_config)?;
+            }
+        }
+        Ok(theme)
+    }
+
+    pub(crate) fn phase(&self, phase: BreathPhase) -> &Style {
+        match phase {
+            BreathPhase::BreathIn => &self.breath_in,
+            BreathPhase::HoldIn => &self.hold_in,
+            BreathPhase::BreathOut => &self.breath_out,
+            BreathPhase::HoldOut => &self.hold_out,
+        }
+    }
+
+    pub(crate) fn session(&self) -> &Style {
+        &self.session
+    }
+}
+
+/// When to use colors
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum ColorMode {
+    Never,
+    /// only on terminals, and if `NO_COLOR` is not set
+    #[default]
+    Auto,
+    Always,
+}
+
+pub(crate) fn set_color_mode(mode: ColorMode) {
+    match mode {
+        ColorMode::Never => console::set_colors_enabled(false),
+        ColorMode::Always => console::set_colors_enabled(true),
+        ColorMode::Auto => {
+            if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+                console::set_colors_enabled(false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builtin_themes() {
+        for name in BUILTIN_THEMES {
+            let theme = Theme::builtin(name);
+            assert!(theme.is_some(), "{name}");
+            let theme = theme.unwrap();
+            for style in [
+                theme.phase(BreathPhase::BreathIn),
+                theme.phase(BreathPhase::HoldIn),
+                theme.phase(BreathPhase::BreathOut),
+                theme.phase(BreathPhase::HoldOut),
+                theme.session(),
+            ] {
+                assert!(style.progress_chars.chars().count() >= 2);
+                assert!(style.spinner_chars.chars().count() >= 2);
+            }
+        }
+        assert!(Theme::builtin("nope").is_none());
+        assert_eq!(Theme::default().session().color, "cyan/blue");
+    }
+
+    #[test]
+    fn theme_from_config() {
+        let config: ThemeConfig = toml::from_str(
+            r#"
+            name = "mono"
+            [breath_in]
+            color = "green/black"
+            [session]
+            progress_chars = "*o."
+            "#,
+        )
+        .unwrap();
+        let theme = Theme::from_config(&config, None).unwrap();
+        assert_eq!(theme.phase(BreathPhase::BreathIn).color, "green/black");
+        assert_eq!(theme.phase(BreathPhase::BreathIn).foreground(), "green");
+        assert_eq!(theme.phase(BreathPhase::BreathOut).color, "white/white");
+        assert_eq!(theme.session().progress_chars, "*o.");
+        let theme = Theme::from_config(&config, Some("forest")).unwrap();
+        assert_eq!(theme.phase(BreathPhase::BreathIn).color, "green/black");
+        assert_eq!(theme.phase(BreathPhase::HoldIn).color, "yellow/black");
+        assert!(Theme::from_config(&config, Some("nope")).is_err());
+        let config: ThemeConfig = toml::from_str("[hold_in]\nspinner_chars = \"x\"").unwrap();
+        assert!(Theme::from_config(&config, None).is_err());
+        let config: ThemeConfig = toml::from_str("[hold_in]\ncolor = \"red; rm\"").unwrap();
+        assert!(Theme::from_config(&config, None).is_err());
+        let config: ThemeConfig = toml::from_str("[session]\nprogress_chars = \"🌕o.\"").unwrap();
+        assert!(Theme::from_config(&config, None).is_err());
+        let config: ThemeConfig = toml::from_str("[session]\nprogress_chars = \"🌕🌗🌑\"").unwrap();
+        assert!(Theme::from_config(&config, None).is_ok());
+    }
+}
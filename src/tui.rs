@@ -1,115 +1,524 @@
 use crate::breathe;
-use crate::config;
+#[cfg(unix)]
+use crate::control;
+use crate::fullscreen;
+use crate::history;
+use crate::hooks;
+use crate::i18n::{self, Message};
+use crate::sound;
+use crate::statusline;
+use crate::text;
+use crate::theme;
+
+/// Messages used as field names in the session summary
+const SUMMARY_FIELDS: [Message; 7] = [
+    Message::Description,
+    Message::BreatheIn,
+    Message::Hold,
+    Message::BreatheOut,
+    Message::SessionLength,
+    Message::EndPolicy,
+    Message::Effective,
+];
+
+/// A field name of the session summary, padded so that all the values are aligned
+pub(crate) fn field_name(message: Message) -> String {
+    padded_field_name(message, &SUMMARY_FIELDS)
+}
+
+/// A field name padded to the longest of `fields`, so that all the values are aligned
+pub(crate) fn padded_field_name(message: Message, fields: &[Message]) -> String {
+    let width = fields
+        .iter()
+        .map(|&field| i18n::t(field).chars().count())
+        .max()
+        .unwrap_or_default();
+    format!(
+        "{:<width$}",
+        format!("{}:", i18n::t(message)),
+        width = width + 1
+    )
+}
 
 fn print_session_opt(opt: &breathe::BreathSessionOpt) {
     let pl = opt.pattern.pattern_length.unwrap();
-    let duration_unit = if matches!(pl, config::PatternLength::Time(_)) {
-        "seconds"
-    } else {
-        ""
-    };
     println!(
-        "Description:   {}
-Breathe in:     {}
-Hold:           {}
-Breathe out:    {}
-Hold:           {}
-Session length: {} {}",
+        "{} {}\n{} {}\n{} {}\n{} {}\n{} {}\n{} {}\n{} {}",
+        field_name(Message::Description),
         opt.pattern.description,
+        field_name(Message::BreatheIn),
         opt.pattern.breath_in,
+        field_name(Message::Hold),
         opt.pattern.hold_in.unwrap_or(0),
+        field_name(Message::BreatheOut),
         opt.pattern.breath_out,
+        field_name(Message::Hold),
         opt.pattern.hold_out.unwrap_or(0),
+        field_name(Message::SessionLength),
         pl,
-        duration_unit
+        field_name(Message::EndPolicy),
+        opt.pattern.end_policy.unwrap_or_default()
     )
 }
 
-use std::sync::{Arc, Mutex};
+use anyhow::anyhow;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 
-pub(crate) fn run(opt: breathe::BreathSessionOpt) {
-    let session = breathe::BreathingSession::with_opt(&opt);
+/// Options about how the session is started and shown
+#[derive(Debug, Default, Clone)]
+pub(crate) struct UiOpt {
+    /// start the session without asking for confirmation
+    pub(crate) yes: bool,
+    /// seconds to wait before the first phase
+    pub(crate) countdown: u64,
+    pub(crate) ui: UiKind,
+    /// text renderer only, print the seconds left in the phase every second
+    pub(crate) ticks: bool,
+    /// animation frames per second
+    pub(crate) fps: u32,
+    pub(crate) statusline_format: statusline::StatuslineFormat,
+    pub(crate) theme: theme::Theme,
+    pub(crate) hooks: hooks::Hooks,
+    /// the name shown to the clients of the control socket
+    pub(crate) pattern_name: String,
+    /// where the session can be controlled, if any
+    pub(crate) socket: Option<std::path::PathBuf>,
+    /// where the session is recorded, if any
+    pub(crate) history: Option<std::path::PathBuf>,
+    /// when a group session starts: no confirmation, and a late member joins in the middle
+    pub(crate) start_at: Option<std::time::SystemTime>,
+}
 
-    print_session_opt(&opt);
-    if matches!(
-        opt.pattern.pattern_length.unwrap(),
-        config::PatternLength::Iterations(_)
-    ) {
-        session.print_params();
-    }
-    let user_choice = dialoguer::Confirm::new()
-        .with_prompt("Would you like to start the breathing session?")
-        .default(true)
-        .interact()
-        .unwrap_or(false);
-    if !user_choice {
-        return;
-    }
-    let mb = indicatif::MultiProgress::new();
-    let pb = indicatif::ProgressBar::new(session.get_lengths_lcm());
-    let pb = mb.add(pb);
-    pb.set_style(
-        indicatif::ProgressStyle::default_bar()
-            .progress_chars("=>-")
-            .tick_chars(r#"-\|/ "#)
-            .template(
-                format!(
-                    "{{spinner:>4}} {{wide_bar:.cyan/blue}} {{msg:<{}}}",
-                    breathe::MAX_BREATHE_PHASE_STR_LEN + 1
-                )
-                .as_str(),
-            )
-            .unwrap(),
-    );
+/// The available renderers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum UiKind {
+    /// progress bars on a terminal, text otherwise
+    #[default]
+    Auto,
+    /// progress bars
+    Bars,
+    /// one line per phase, for screen readers and logs
+    Text,
+    /// a full screen animation, with a shape following the breath
+    Fullscreen,
+    /// a line every second, for the status bars
+    Statusline,
+}
 
-    let total_pb = indicatif::ProgressBar::new(session.session_length);
-    let total_pb = mb.add(total_pb);
-    total_pb.set_style(
-        indicatif::ProgressStyle::with_template(
+/// Something showing the progress of a running session
+pub(crate) trait Renderer: Send {
+    /// Called once, before the first second of the session
+    fn start(&mut self, session: &breathe::BreathingSession);
+    /// Called every second, after the session has been updated
+    fn tick(&mut self, session: &breathe::BreathingSession);
+    /// Called between two seconds, to animate the progress of the phase.
+    /// `sub_second`, from 0 to 1, is the time passed since the last `tick`
+    fn frame(&mut self, _session: &breathe::BreathingSession, _sub_second: f64) {}
+    /// Called once, when the session is completed or aborted
+    fn finish(&mut self, session: &breathe::BreathingSession);
+}
+
+/// Sub-steps of the phase progress bar, for each step of the lcm of the phase lengths.
+/// The lcm keeps the whole seconds on exact positions, the sub-steps allow smooth animations
+const PHASE_BAR_SUB_STEPS: u64 = 100;
+
+/// The default renderer, with a progress bar for the phase and one for the whole session
+struct BarsRenderer {
+    mb: indicatif::MultiProgress,
+    pb: indicatif::ProgressBar,
+    total_pb: indicatif::ProgressBar,
+    theme: theme::Theme,
+    /// both bars end at the same column, leaving room for the longest label
+    label_width: usize,
+}
+
+fn phase_bar_style(style: &theme::Style, label_width: usize) -> indicatif::ProgressStyle {
+    indicatif::ProgressStyle::default_bar()
+        .progress_chars(&style.progress_chars)
+        .tick_chars(&style.spinner_chars)
+        .template(
             format!(
-                "{{percent:>3}}% {{wide_bar:.cyan/blue}} {{eta:<{}}}",
-                breathe::MAX_BREATHE_PHASE_STR_LEN + 1
+                "{{spinner:>4}} {{wide_bar:.{}}} {{msg:<{}}}",
+                style.color,
+                label_width + 1
             )
             .as_str(),
         )
         .unwrap()
-        .progress_chars("=>-"),
-    );
-    let session = Arc::new(Mutex::new(session));
+}
+
+fn session_bar_style(style: &theme::Style, label_width: usize) -> indicatif::ProgressStyle {
+    indicatif::ProgressStyle::with_template(
+        format!(
+            "{{percent:>3}}% {{wide_bar:.{}}} {{eta:<{}}}",
+            style.color,
+            label_width + 1
+        )
+        .as_str(),
+    )
+    .unwrap()
+    .progress_chars(&style.progress_chars)
+}
+
+impl BarsRenderer {
+    fn new(session: &breathe::BreathingSession, theme: theme::Theme) -> Self {
+        let mb = indicatif::MultiProgress::new();
+        let pb = indicatif::ProgressBar::new(session.get_lengths_lcm() * PHASE_BAR_SUB_STEPS);
+        let pb = mb.add(pb);
+        let label_width = session.max_label_width();
+        pb.set_style(phase_bar_style(
+            theme.phase(session.current_state),
+            label_width,
+        ));
+
+        let total_pb = indicatif::ProgressBar::new(session.session_length);
+        let total_pb = mb.add(total_pb);
+        total_pb.set_style(session_bar_style(theme.session(), label_width));
+        BarsRenderer {
+            mb,
+            pb,
+            total_pb,
+            theme,
+            label_width,
+        }
+    }
+
+    fn set_phase_position(&self, session: &breathe::BreathingSession, sub_second: f64) {
+        let length = self.pb.length().unwrap_or_default();
+        let progress = session.eased_phase_progress(sub_second);
+        self.pb
+            .set_position((progress * length as f64).round() as u64);
+    }
+}
+
+impl Renderer for BarsRenderer {
+    fn start(&mut self, session: &breathe::BreathingSession) {
+        self.pb.set_message(session.current_label().to_string());
+        self.total_pb.reset();
+        // a late member of a group session starts in the middle
+        self.total_pb.set_position(session.elapsed());
+        self.set_phase_position(session, 0.0);
+    }
+
+    fn tick(&mut self, session: &breathe::BreathingSession) {
+        self.total_pb.inc(1);
+        if session.is_state_changed() {
+            self.pb.set_style(phase_bar_style(
+                self.theme.phase(session.current_state),
+                self.label_width,
+            ));
+            self.pb.set_message(session.current_label().to_string());
+        }
+        self.set_phase_position(session, 0.0);
+    }
+
+    fn frame(&mut self, session: &breathe::BreathingSession, sub_second: f64) {
+        self.set_phase_position(session, sub_second);
+    }
+
+    fn finish(&mut self, _session: &breathe::BreathingSession) {
+        self.mb.clear().unwrap();
+    }
+}
+
+fn confirm() -> anyhow::Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Err(anyhow!(
+            "Cannot ask for confirmation, stdin is not a terminal: use --yes to start the session anyway"
+        ));
+    }
+    Ok(dialoguer::Confirm::new()
+        .with_prompt(i18n::t(Message::StartPrompt))
+        .default(true)
+        .interact()
+        .unwrap_or(false))
+}
+
+fn countdown(seconds: u64) {
+    for i in (1..=seconds).rev() {
+        println!("{} {i}...", i18n::t(Message::StartingIn));
+        thread::sleep(std::time::Duration::from_secs(1));
+    }
+}
+
+/// Wait for the start of a group session, printing the seconds left if `verbose`.
+/// A late member skips the seconds already practiced by the others
+fn wait_until(
+    start_at: std::time::SystemTime,
+    session: &mut breathe::BreathingSession,
+    verbose: bool,
+) -> std::time::Instant {
+    let now = std::time::Instant::now();
+    match start_at.duration_since(std::time::SystemTime::now()) {
+        Ok(wait) => {
+            let start = now + wait;
+            loop {
+                let left = start.saturating_duration_since(std::time::Instant::now());
+                if left.is_zero() {
+                    return start;
+                }
+                let seconds = left.as_secs() + u64::from(left.subsec_nanos() > 0);
+                if verbose {
+                    println!("{} {seconds}...", i18n::t(Message::StartingIn));
+                }
+                thread::sleep(left - std::time::Duration::from_secs(seconds - 1));
+            }
+        }
+        Err(e) => {
+            let late = e.duration();
+            while session.elapsed() < late.as_secs() && !session.is_completed() {
+                session.inc();
+            }
+            now.checked_sub(late).unwrap_or(now)
+        }
+    }
+}
+
+/// Run the session, returning false if it has been aborted
+pub(crate) fn run(opt: breathe::BreathSessionOpt, ui_opt: UiOpt) -> anyhow::Result<bool> {
+    let mut session = breathe::BreathingSession::with_opt(&opt)?;
+
+    // the status bars show only the output of the renderer, and can't answer questions
+    if ui_opt.ui != UiKind::Statusline {
+        print_session_opt(&opt);
+        session.print_params();
+        if ui_opt.start_at.is_none() {
+            if !ui_opt.yes && !confirm()? {
+                return Ok(true);
+            }
+            countdown(ui_opt.countdown);
+        }
+    }
+    let started = match ui_opt.start_at {
+        Some(start_at) => wait_until(start_at, &mut session, ui_opt.ui != UiKind::Statusline),
+        None => std::time::Instant::now(),
+    };
+    let ui = match ui_opt.ui {
+        // progress bars are useless when the output is not a terminal
+        UiKind::Auto if std::io::stdout().is_terminal() => UiKind::Bars,
+        UiKind::Auto => UiKind::Text,
+        ui => ui,
+    };
+    // the text renderers don't animate anything
+    let fps = if matches!(ui, UiKind::Text | UiKind::Statusline) {
+        1
+    } else {
+        ui_opt.fps
+    };
+    let renderer: Box<dyn Renderer> = match ui {
+        UiKind::Text => Box::new(text::TextRenderer {
+            ticks: ui_opt.ticks,
+        }),
+        UiKind::Fullscreen => Box::new(fullscreen::FullScreenRenderer::new(ui_opt.theme)),
+        UiKind::Statusline => Box::new(statusline::StatuslineRenderer {
+            format: ui_opt.statusline_format,
+        }),
+        _ => Box::new(BarsRenderer::new(&session, ui_opt.theme)),
+    };
+    let aborted = Arc::new(AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        signal_hook::flag::register(signal, aborted.clone())?;
+    }
+    let listeners = Listeners {
+        cues: sound::Cues::new(opt.pattern.sound.clone()),
+        hooks: ui_opt.hooks,
+        #[cfg(unix)]
+        control: ui_opt.socket.as_deref().and_then(|path| {
+            control::Server::start(path, &ui_opt.pattern_name, aborted.clone())
+                .map_err(|e| log::warn!("The session can't be controlled: {e:#}"))
+                .ok()
+        }),
+    };
+    let start = chrono::Local::now();
+    let outcome = drive(session, renderer, listeners, aborted, fps, started);
+    if let Some(path) = &ui_opt.history {
+        let entry = history::Entry {
+            pattern: ui_opt.pattern_name.clone(),
+            start,
+            end: chrono::Local::now(),
+            elapsed: outcome.elapsed,
+            completed: outcome.completed,
+        };
+        if let Err(e) = history::append(path, &entry) {
+            log::warn!("The session can't be saved in the history: {e:#}");
+        }
+    }
+    Ok(outcome.completed)
+}
+
+/// Everything following the session besides the renderer
+struct Listeners {
+    cues: sound::Cues,
+    hooks: hooks::Hooks,
+    #[cfg(unix)]
+    control: Option<control::Server>,
+}
+
+impl Listeners {
+    fn notify(&self, event: hooks::Event, session: &breathe::BreathingSession, paused: bool) {
+        if event == hooks::Event::PhaseChange {
+            self.cues.phase_started(session.current_state);
+        }
+        self.hooks.run(event, session);
+        #[cfg(unix)]
+        if let Some(control) = &self.control {
+            control.publish(event, session, paused);
+        }
+    }
+
+    /// Called every second
+    fn update(&self, _session: &breathe::BreathingSession, _paused: bool) {
+        #[cfg(unix)]
+        if let Some(control) = &self.control {
+            control.update(_session, _paused);
+        }
+    }
+
+    /// Called once the session is over
+    fn close(&self) {
+        #[cfg(unix)]
+        if let Some(control) = &self.control {
+            control.close();
+        }
+    }
+
+    fn pause_requested(&self) -> bool {
+        #[cfg(unix)]
+        if let Some(control) = &self.control {
+            return control.is_paused();
+        }
+        false
+    }
+}
+
+/// The wall clock of the session, it can be paused
+struct Clock {
+    start: std::time::Instant,
+    paused_at: Option<std::time::Instant>,
+    paused_for: std::time::Duration,
+}
+
+impl Clock {
+    fn new(start: std::time::Instant) -> Self {
+        Clock {
+            start,
+            paused_at: None,
+            paused_for: std::time::Duration::ZERO,
+        }
+    }
+
+    fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    fn pause(&mut self) {
+        self.paused_at.get_or_insert_with(std::time::Instant::now);
+    }
+
+    fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused_for += paused_at.elapsed();
+        }
+    }
+
+    /// Time spent running, without the pauses
+    fn elapsed(&self) -> std::time::Duration {
+        let now = self.paused_at.unwrap_or_else(std::time::Instant::now);
+        now.duration_since(self.start)
+            .saturating_sub(self.paused_for)
+    }
+}
+
+/// How a session ended
+struct Outcome {
+    /// false if the session has been aborted, by a signal or by a client
+    completed: bool,
+    /// seconds practiced
+    elapsed: u64,
+}
+
+/// Run the session until it's completed, drawing `fps` frames per second.
+/// The session moves forward following the wall clock from `start`, one second at a time.
+fn drive(
+    session: breathe::BreathingSession,
+    mut renderer: Box<dyn Renderer>,
+    listeners: Listeners,
+    aborted: Arc<AtomicBool>,
+    fps: u32,
+    start: std::time::Instant,
+) -> Outcome {
+    renderer.start(&session);
+    listeners.update(&session, false);
+    listeners.notify(hooks::Event::SessionStart, &session, false);
+    if session.is_completed() {
+        renderer.finish(&session);
+        listeners.notify(hooks::Event::SessionEnd, &session, false);
+        listeners.close();
+        return Outcome {
+            completed: true,
+            elapsed: 0,
+        };
+    }
+    listeners.notify(hooks::Event::PhaseChange, &session, false);
+    let state = Arc::new(Mutex::new((session, renderer)));
+    let (tx, rx) = mpsc::channel();
     let timer = timer::Timer::new();
+    let mut clock = Clock::new(start);
+    // the timer can call again before being dropped
+    let mut aborted_reported = false;
+    let period = chrono::Duration::microseconds(1_000_000 / i64::from(fps.max(1)));
+    let listeners = Arc::new(listeners);
     let guard = {
-        let session = session.clone();
-        pb.set_message(session.lock().unwrap().phase_as_str());
-        total_pb.reset();
-        let mb = mb.clone();
-        timer.schedule_repeating(chrono::Duration::seconds(1), move || {
-            let mut session = session.lock().unwrap();
-            if !session.is_completed() {
+        let state = state.clone();
+        let listeners = listeners.clone();
+        timer.schedule_repeating(period, move || {
+            let mut state = state.lock().unwrap();
+            let (session, renderer) = &mut *state;
+            if session.is_completed() || aborted_reported {
+                return;
+            }
+            if aborted.load(Ordering::Relaxed) {
+                aborted_reported = true;
+                renderer.finish(session);
+                listeners.notify(hooks::Event::Abort, session, clock.is_paused());
+                let _ = tx.send(false);
+                return;
+            }
+            let pause_requested = listeners.pause_requested();
+            if pause_requested && !clock.is_paused() {
+                clock.pause();
+                listeners.notify(hooks::Event::Pause, session, true);
+            } else if !pause_requested && clock.is_paused() {
+                clock.resume();
+                listeners.notify(hooks::Event::Resume, session, false);
+            }
+            if clock.is_paused() {
+                return;
+            }
+            let elapsed = clock.elapsed();
+            while session.elapsed() < elapsed.as_secs() && !session.is_completed() {
                 session.inc();
-                total_pb.inc(1);
-                if session.is_state_changed() {
-                    pb.inc(session.get_lengths_lcm() / session.get_current_phase_length());
-                    pb.set_message(session.phase_as_str());
-                    pb.dec(pb.position());
-                } else {
-                    pb.inc(session.get_lengths_lcm() / session.get_current_phase_length());
+                renderer.tick(session);
+                listeners.update(session, false);
+                if session.is_state_changed() && !session.is_completed() {
+                    listeners.notify(hooks::Event::PhaseChange, session, false);
                 }
+            }
+            if session.is_completed() {
+                renderer.finish(session);
+                listeners.notify(hooks::Event::SessionEnd, session, false);
+                let _ = tx.send(true);
             } else {
-                mb.clear().unwrap();
+                renderer.frame(session, f64::from(elapsed.subsec_millis()) / 1000.0);
             }
         })
     };
-    loop {
-        thread::sleep(std::time::Duration::new(0, 501));
-        {
-            let session = session.clone();
-            let session = session.lock().unwrap();
-            if session.is_completed() {
-                mb.clear().unwrap();
-                break;
-            }
-        }
-    }
+    let completed = rx.recv().unwrap_or(false);
     drop(guard);
+    listeners.close();
+    let elapsed = state.lock().unwrap().0.elapsed();
+    Outcome { completed, elapsed }
 }
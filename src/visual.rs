@@ -0,0 +1,531 @@
+use crate::breathe::{self, BreathPhase};
+use crate::config::Pattern;
+use anyhow::Context;
+use std::fmt::Write as _;
+
+/// Radius of the shape, relative to the image size, with empty and full lungs
+const MIN_RADIUS: f64 = 0.15;
+const MAX_RADIUS: f64 = 0.38;
+/// Vertical position of the center of the shape, relative to the image size
+const CENTER_Y: f64 = 0.44;
+/// Samples per second of the SVG animation of the radius
+const SVG_SAMPLES: u64 = 10;
+
+const BACKGROUND: [u8; 3] = [0xff, 0xff, 0xff];
+const TEXT: [u8; 3] = [0x33, 0x33, 0x33];
+
+/// The available formats of the animation
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum VisualFormat {
+    #[default]
+    Svg,
+    Gif,
+}
+
+/// Parameters of the animation
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct VisualOpt {
+    pub(crate) format: VisualFormat,
+    /// width and height, in pixels
+    pub(crate) size: u16,
+    /// GIF only, frames per second
+    pub(crate) fps: u32,
+}
+
+fn phase_color(phase: BreathPhase) -> [u8; 3] {
+    match phase {
+        BreathPhase::BreathIn => [0x4a, 0x90, 0xd9],
+        BreathPhase::HoldIn => [0x7b, 0x68, 0xee],
+        BreathPhase::BreathOut => [0x3c, 0xb3, 0x71],
+        BreathPhase::HoldOut => [0xe0, 0xa0, 0x40],
+    }
+}
+
+fn hex(color: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2])
+}
+
+/// One cycle of a pattern, the only thing needed to draw the animation
+struct Cycle<'a> {
+    phases: Vec<(BreathPhase, u64)>,
+    length: u64,
+    pattern: &'a Pattern,
+}
+
+impl<'a> Cycle<'a> {
+    fn new(pattern: &'a Pattern) -> Result<Self, breathe::SessionError> {
+        let cycle = breathe::from_pattern(pattern);
+        if cycle.cycle_length == 0 {
+            return Err(breathe::SessionError::EmptyCycle);
+        }
+        Ok(Cycle {
+            phases: cycle.phases(),
+            length: cycle.cycle_length,
+            pattern,
+        })
+    }
+
+    fn label(&self, phase: BreathPhase) -> &str {
+        breathe::phase_label(&self.pattern.labels, phase)
+    }
+
+    /// The phases with the second they start at
+    fn starts(&self) -> impl Iterator<Item = (BreathPhase, u64, u64)> + '_ {
+        self.phases.iter().scan(0, |start, &(phase, length)| {
+            let item = (phase, *start, length);
+            *start += length;
+            Some(item)
+        })
+    }
+
+    /// The phase at `t` seconds from the beginning of the cycle, and how full the lungs are
+    fn shape_at(&self, t: f64) -> (BreathPhase, f64) {
+        let (phase, start, length) = self
+            .starts()
+            .take_while(|&(_, start, _)| start as f64 <= t)
+            .last()
+            .unwrap_or((self.phases[0].0, 0, self.phases[0].1));
+        let progress = phase.easing().apply((t - start as f64) / length as f64);
+        (phase, phase.fill(progress))
+    }
+}
+
+fn radius(size: f64, fill: f64) -> f64 {
+    size * (MIN_RADIUS + (MAX_RADIUS - MIN_RADIUS) * fill)
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A looping SVG animation of one cycle, animated with SMIL
+fn svg(cycle: &Cycle, size: u16) -> String {
+    let size_f = f64::from(size);
+    let length = cycle.length as f64;
+    let samples = cycle.length * SVG_SAMPLES;
+    let (mut radii, mut times) = (Vec::new(), Vec::new());
+    for i in 0..=samples {
+        let t = i as f64 / SVG_SAMPLES as f64;
+        // the last sample is the beginning of the next cycle
+        let (_, fill) = cycle.shape_at(if i == samples { 0.0 } else { t });
+        radii.push(format!("{:.2}", radius(size_f, fill)));
+        times.push(format!("{:.4}", t / length));
+    }
+    let (mut colors, mut color_times) = (Vec::new(), Vec::new());
+    for (phase, start, _) in cycle.starts() {
+        colors.push(hex(phase_color(phase)));
+        color_times.push(format!("{:.4}", start as f64 / length));
+    }
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {size} {size}">"#
+    );
+    let _ = writeln!(
+        out,
+        r#"  <rect width="100%" height="100%" fill="{}"/>"#,
+        hex(BACKGROUND)
+    );
+    let _ = writeln!(
+        out,
+        r#"  <circle cx="{:.2}" cy="{:.2}" r="{}" fill="{}">"#,
+        size_f / 2.0,
+        size_f * CENTER_Y,
+        radii[0],
+        colors[0]
+    );
+    let _ = writeln!(
+        out,
+        r#"    <animate attributeName="r" dur="{}s" repeatCount="indefinite" values="{}" keyTimes="{}"/>"#,
+        cycle.length,
+        radii.join(";"),
+        times.join(";")
+    );
+    let _ = writeln!(
+        out,
+        r#"    <animate attributeName="fill" dur="{}s" repeatCount="indefinite" calcMode="discrete" values="{}" keyTimes="{}"/>"#,
+        cycle.length,
+        colors.join(";"),
+        color_times.join(";")
+    );
+    let _ = writeln!(out, "  </circle>");
+    for (phase, start, phase_length) in cycle.starts() {
+        let (start, end) = (
+            start as f64 / length,
+            (start + phase_length) as f64 / length,
+        );
+        let mut opacity = Vec::new();
+        if start > 0.0 {
+            opacity.push(("0", 0.0));
+        }
+        opacity.push(("1", start));
+        if end < 1.0 {
+            opacity.push(("0", end));
+        }
+        let _ = writeln!(
+            out,
+            r#"  <text x="50%" y="{:.2}" text-anchor="middle" font-family="sans-serif" font-size="{:.0}" fill="{}" opacity="{}">{}"#,
+            size_f * 0.93,
+            size_f / 12.0,
+            hex(TEXT),
+            opacity[0].0,
+            xml_escape(cycle.label(phase))
+        );
+        let _ = writeln!(
+            out,
+            r#"    <animate attributeName="opacity" dur="{}s" repeatCount="indefinite" calcMode="discrete" values="{}" keyTimes="{}"/>"#,
+            cycle.length,
+            opacity
+                .iter()
+                .map(|(v, _)| *v)
+                .collect::<Vec<_>>()
+                .join(";"),
+            opacity
+                .iter()
+                .map(|(_, t)| format!("{t:.4}"))
+                .collect::<Vec<_>>()
+                .join(";")
+        );
+        let _ = writeln!(out, "  </text>");
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+/// Palette indexes of the GIF frames
+const BACKGROUND_INDEX: u8 = 0;
+const TEXT_INDEX: u8 = 1;
+
+fn phase_index(phase: BreathPhase) -> u8 {
+    match phase {
+        BreathPhase::BreathIn => 2,
+        BreathPhase::HoldIn => 3,
+        BreathPhase::BreathOut => 4,
+        BreathPhase::HoldOut => 5,
+    }
+}
+
+fn gif_palette() -> Vec<u8> {
+    [
+        BACKGROUND,
+        TEXT,
+        phase_color(BreathPhase::BreathIn),
+        phase_color(BreathPhase::HoldIn),
+        phase_color(BreathPhase::BreathOut),
+        phase_color(BreathPhase::HoldOut),
+        BACKGROUND,
+        BACKGROUND,
+    ]
+    .concat()
+}
+
+/// Draw a frame as palette indexes
+fn draw_frame(cycle: &Cycle, size: u16, t: f64) -> Vec<u8> {
+    let size = usize::from(size);
+    let size_f = size as f64;
+    let (phase, fill) = cycle.shape_at(t);
+    let r = radius(size_f, fill);
+    let (cx, cy) = (size_f / 2.0, size_f * CENTER_Y);
+    let mut pixels = vec![BACKGROUND_INDEX; size * size];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let (x, y) = ((i % size) as f64 + 0.5, (i / size) as f64 + 0.5);
+        if (x - cx).powi(2) + (y - cy).powi(2) <= r * r {
+            *pixel = phase_index(phase);
+        }
+    }
+    // the label, centered at the bottom
+    let scale = (size / 120).max(1);
+    let label: Vec<char> = cycle.label(phase).chars().collect();
+    let max_chars = size / (6 * scale);
+    let label = &label[..label.len().min(max_chars)];
+    let width = (label.len() * 6).saturating_sub(1) * scale;
+    let left = (size - width) / 2;
+    let top = size - size / 20 - 7 * scale;
+    for (n, &c) in label.iter().enumerate() {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..5 {
+                if bits & (0b10000 >> col) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let x = left + (n * 6 + col) * scale + dx;
+                        let y = top + row * scale + dy;
+                        pixels[y * size + x] = TEXT_INDEX;
+                    }
+                }
+            }
+        }
+    }
+    pixels
+}
+
+/// A looping GIF animation of one cycle, consecutive identical frames are merged
+fn gif<W: std::io::Write>(cycle: &Cycle, size: u16, fps: u32, writer: W) -> anyhow::Result<()> {
+    let mut encoder = gif::Encoder::new(writer, size, size, &gif_palette())?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    let frames = cycle.length * u64::from(fps);
+    // delays are in hundredths of a second, rounded without accumulating errors
+    let delay_at = |frame: u64| (frame * 100 + u64::from(fps) / 2) / u64::from(fps);
+    let mut previous: Option<(Vec<u8>, u64)> = None;
+    let mut write = |pixels: Vec<u8>, delay: u64| -> anyhow::Result<()> {
+        let mut frame = gif::Frame::from_indexed_pixels(size, size, pixels, None);
+        frame.delay = u16::try_from(delay).unwrap_or(u16::MAX);
+        encoder.write_frame(&frame)?;
+        Ok(())
+    };
+    for i in 0..frames {
+        let pixels = draw_frame(cycle, size, i as f64 / f64::from(fps));
+        let delay = delay_at(i + 1) - delay_at(i);
+        previous = match previous {
+            Some((last, last_delay)) if last == pixels => Some((last, last_delay + delay)),
+            Some((last, last_delay)) => {
+                write(last, last_delay)?;
+                Some((pixels, delay))
+            }
+            None => Some((pixels, delay)),
+        };
+    }
+    if let Some((last, last_delay)) = previous {
+        write(last, last_delay)?;
+    }
+    Ok(())
+}
+
+/// Render one cycle of the pattern as an animation, saved in `output`
+pub(crate) fn render(
+    pattern: &Pattern,
+    opt: &VisualOpt,
+    output: &std::path::Path,
+) -> anyhow::Result<()> {
+    let cycle = Cycle::new(pattern)?;
+    let file = std::fs::File::create(output)
+        .with_context(|| format!("Failed to create {}", output.display()))?;
+    let mut writer = std::io::BufWriter::new(file);
+    match opt.format {
+        VisualFormat::Svg => {
+            std::io::Write::write_all(&mut writer, svg(&cycle, opt.size).as_bytes())?;
+            std::io::Write::flush(&mut writer)?;
+        }
+        VisualFormat::Gif => gif(&cycle, opt.size, opt.fps, writer)?,
+    }
+    Ok(())
+}
+
+/// A 5x7 bitmap font, one byte per row. Letters are shown uppercase, accents are dropped
+fn glyph(c: char) -> [u8; 7] {
+    let c = match c.to_ascii_uppercase() {
+        'à' | 'á' | 'À' | 'Á' => 'A',
+        'è' | 'é' | 'È' | 'É' => 'E',
+        'ì' | 'í' | 'Ì' | 'Í' => 'I',
+        'ò' | 'ó' | 'Ò' | 'Ó' => 'O',
+        'ù' | 'ú' | 'Ù' | 'Ú' => 'U',
+        c => c,
+    };
+    match c {
+        ' ' => [0; 7],
+        'A' => [
+            0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+        'B' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+        ],
+        'C' => [
+            0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+        ],
+        'D' => [
+            0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110,
+        ],
+        'E' => [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+        ],
+        'F' => [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+        'G' => [
+            0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+        ],
+        'H' => [
+            0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+        'I' => [
+            0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+        'J' => [
+            0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+        ],
+        'K' => [
+            0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+        ],
+        'L' => [
+            0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+        ],
+        'M' => [
+            0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+        ],
+        'N' => [
+            0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+        ],
+        'O' => [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+        'P' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+        'Q' => [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+        ],
+        'R' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+        ],
+        'S' => [
+            0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+        ],
+        'T' => [
+            0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+        'U' => [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+        'V' => [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+        ],
+        'W' => [
+            0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+        ],
+        'X' => [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+        ],
+        'Y' => [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+        'Z' => [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+        ],
+        '0' => [
+            0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+        ],
+        '1' => [
+            0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+        '2' => [
+            0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+        ],
+        '3' => [
+            0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+        ],
+        '4' => [
+            0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+        ],
+        '5' => [
+            0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+        ],
+        '6' => [
+            0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+        ],
+        '7' => [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+        ],
+        '8' => [
+            0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+        ],
+        '9' => [
+            0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+        ],
+        '-' => [0, 0, 0, 0b11111, 0, 0, 0],
+        '.' => [0, 0, 0, 0, 0, 0b01100, 0b01100],
+        ',' => [0, 0, 0, 0, 0b01100, 0b00100, 0b01000],
+        '\'' => [0b00100, 0b00100, 0b01000, 0, 0, 0, 0],
+        '!' => [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0, 0b00100],
+        _ => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0, 0b00100],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pattern() -> Pattern {
+        Pattern {
+            hold_in: Some(7),
+            breath_out: 8,
+            ..crate::breathe::test::pattern()
+        }
+    }
+
+    #[test]
+    fn cycle_shape() {
+        let pattern = pattern();
+        let cycle = Cycle::new(&pattern).unwrap();
+        assert_eq!(cycle.length, 19);
+        assert_eq!(cycle.shape_at(0.0), (BreathPhase::BreathIn, 0.0));
+        assert_eq!(cycle.shape_at(5.0), (BreathPhase::HoldIn, 1.0));
+        assert_eq!(cycle.shape_at(11.0), (BreathPhase::BreathOut, 1.0));
+        let (phase, fill) = cycle.shape_at(15.0);
+        assert_eq!(phase, BreathPhase::BreathOut);
+        assert!(fill > 0.0 && fill < 1.0);
+        assert!(Cycle::new(&Pattern::default()).is_err());
+    }
+
+    #[test]
+    fn svg_animation() {
+        let mut pattern = pattern();
+        pattern.labels.hold_in = Some("Hold <gently>".to_string());
+        let cycle = Cycle::new(&pattern).unwrap();
+        let got = svg(&cycle, 200);
+        assert!(got.starts_with("<svg "));
+        assert!(got.ends_with("</svg>\n"));
+        assert_eq!(got.matches(r#"dur="19s""#).count(), 5);
+        assert!(got.contains(">Breathe in\n"));
+        assert!(got.contains(">Hold &lt;gently&gt;\n"));
+        assert!(
+            got.contains(r##"values="#4a90d9;#7b68ee;#3cb371" keyTimes="0.0000;0.2105;0.5789""##)
+        );
+        // the radius goes back to the start, for a seamless loop
+        assert!(got.contains(r#"values="30.00;"#));
+        assert!(got.contains(r#";30.00" keyTimes="0.0000;"#));
+        assert!(got.contains(r#"values="0;1;0" keyTimes="0.0000;0.2105;0.5789""#));
+    }
+
+    #[test]
+    fn gif_animation() {
+        let pattern = pattern();
+        let cycle = Cycle::new(&pattern).unwrap();
+        let mut out = Vec::new();
+        gif(&cycle, 120, 10, &mut out).unwrap();
+        let mut decoder = gif::DecodeOptions::new().read_info(out.as_slice()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (120, 120));
+        let (mut frames, mut duration) = (0, 0);
+        let mut colored = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames += 1;
+            duration += u64::from(frame.delay);
+            colored.push(
+                frame
+                    .buffer
+                    .iter()
+                    .filter(|&&p| p != BACKGROUND_INDEX && p != TEXT_INDEX)
+                    .count(),
+            );
+        }
+        assert_eq!(duration, 1900);
+        // the hold is a single frame
+        assert!(frames < 190 - 60);
+        // the shape grows, then shrinks
+        assert!(colored[0] < colored[20]);
+        assert!(colored[frames - 1] < colored[30]);
+    }
+
+    #[test]
+    fn gif_label() {
+        let pattern = pattern();
+        let cycle = Cycle::new(&pattern).unwrap();
+        let pixels = draw_frame(&cycle, 120, 0.0);
+        assert!(pixels.contains(&TEXT_INDEX));
+        assert_eq!(glyph('a'), glyph('A'));
+        assert_eq!(glyph('è'), glyph('E'));
+    }
+}